@@ -1,12 +1,12 @@
 #!/bin/bash
 set -e
 
-cd zkpop/host
+cd "$(dirname "$0")/../zkproofofpayment/host"
 
 echo "Running Merkle proof tests..."
-cargo run --release -- merkle
+cargo run --release -- vectors merkle
 
 echo "Running Audit proof tests..."
-cargo run --release -- audit
+cargo run --release -- vectors audit
 
-echo "All tests completed." 
\ No newline at end of file
+echo "All tests completed."
@@ -1,5 +1,6 @@
 #![no_std]
-use poseidon::{Poseidon, Fr};
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
 use crate::merkle::{poseidon_hash2, Hash};
 
 pub fn verify_audit_proof(
@@ -29,12 +30,8 @@ pub fn verify_audit_proof(
 
 // Overload for hashing a [u8; 32] and [u8; 8] (for amount)
 pub fn poseidon_hash2_amount(a: [u8; 32], b: [u8; 8]) -> [u8; 32] {
-    let a_fr = Fr::from_bytes(&a).unwrap();
     let mut b_padded = [0u8; 32];
     b_padded[24..].copy_from_slice(&b);
-    let b_fr = Fr::from_bytes(&b_padded).unwrap();
-    let mut poseidon = Poseidon::new();
-    poseidon.update(&[a_fr, b_fr]);
-    let hash_fr = poseidon.finalize();
-    hash_fr.to_bytes()
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+    poseidon.hash_bytes_be(&[&a, &b_padded]).unwrap()
 } 
\ No newline at end of file
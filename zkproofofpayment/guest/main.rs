@@ -1,6 +1,7 @@
 #![no_std]
 use risc0_zkvm::guest::env;
-use poseidon::{Poseidon, Fr};
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
 
 // Constants
 const DEPTH: usize = 32;
@@ -44,24 +45,16 @@ fn main() {
 
 // Real Poseidon hash of two elements (32 bytes each)
 fn poseidon_hash2(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
-    let a_fr = Fr::from_bytes(&a).unwrap();
-    let b_fr = Fr::from_bytes(&b).unwrap();
-    let mut poseidon = Poseidon::new();
-    poseidon.update(&[a_fr, b_fr]);
-    let hash_fr = poseidon.finalize();
-    hash_fr.to_bytes()
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+    poseidon.hash_bytes_be(&[&a, &b]).unwrap()
 }
 
 // Overload for hashing a [u8; 32] and [u8; 8] (for amount)
 fn poseidon_hash2(a: [u8; 32], b: [u8; 8]) -> [u8; 32] {
-    let a_fr = Fr::from_bytes(&a).unwrap();
     let mut b_padded = [0u8; 32];
     b_padded[24..].copy_from_slice(&b);
-    let b_fr = Fr::from_bytes(&b_padded).unwrap();
-    let mut poseidon = Poseidon::new();
-    poseidon.update(&[a_fr, b_fr]);
-    let hash_fr = poseidon.finalize();
-    hash_fr.to_bytes()
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+    poseidon.hash_bytes_be(&[&a, &b_padded]).unwrap()
 }
 
 pub fn generate_proof(input: &str) -> String {
@@ -0,0 +1,53 @@
+#![no_std]
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+use risc0_zkvm::guest::env;
+mod merkle;
+mod note;
+use merkle::{verify_merkle_proof, Hash, DEPTH};
+use note::{note_commitment, timestamped_leaf};
+
+fn main() {
+    // Read public inputs: the note tree root and the filter
+    let merkle_root: Hash = env::read();
+    let recipient: String = env::read();
+    let start_time: u64 = env::read(); // inclusive
+    let end_time: u64 = env::read();   // exclusive
+
+    // Read private note openings with their Merkle paths
+    let note_count: u32 = env::read();
+    let mut leaves: Vec<Hash> = Vec::with_capacity(note_count as usize);
+    let mut min_total: u64 = 0;
+    for _ in 0..note_count {
+        let amount: u64 = env::read();
+        let note_recipient: String = env::read();
+        let memo: Option<String> = env::read();
+        let blinding: Hash = env::read();
+        let timestamp: u64 = env::read();
+        let path_elements: [Hash; DEPTH] = env::read();
+        let path_indices: [u8; DEPTH] = env::read();
+
+        // 1. Open the note commitment and check its leaf is in the tree
+        let commitment = note_commitment(amount, &note_recipient, memo.as_deref(), blinding);
+        let leaf = timestamped_leaf(commitment, timestamp);
+        assert!(verify_merkle_proof(leaf, merkle_root, path_elements, path_indices));
+        leaves.push(leaf);
+
+        // 2. Sum the notes that match the filter. The prover may leave matching notes out, so
+        //    this is only a lower bound on the payments made.
+        if note_recipient == recipient && timestamp >= start_time && timestamp < end_time {
+            min_total = min_total.checked_add(amount).expect("total overflows u64");
+        }
+    }
+
+    // 3. Each note may be counted only once
+    leaves.sort_unstable();
+    assert!(leaves.windows(2).all(|w| w[0] != w[1]));
+
+    // Commit only the root, the filter and the lower bound
+    env::commit(&merkle_root);
+    env::commit(&recipient);
+    env::commit(&start_time);
+    env::commit(&end_time);
+    env::commit(&min_total);
+}
@@ -1,4 +1,6 @@
 #![no_std]
+extern crate alloc;
+use alloc::vec::Vec;
 use risc0_zkvm::guest::env;
 mod merkle;
 mod audit;
@@ -25,6 +27,29 @@ fn main() {
         merkle_root,
         CURRENT_TIME,
     );
+
+    // Optional composition: a transfer receipt passed to the prover as an assumption.
+    // The journal is read raw so it can be checked byte-for-byte against the receipt.
+    let transfer_image_id: Option<[u32; 8]> = env::read();
+    let transfer_link = transfer_image_id.map(|image_id| {
+        let journal: Vec<u8> = env::read();
+        env::verify(image_id, &journal).expect("transfer receipt does not verify");
+        let (out_nullifier, transfer_valid, out_commitment, transfer_root): (Hash, bool, Hash, Hash) =
+            risc0_zkvm::serde::from_slice(&journal).expect("malformed transfer journal");
+        // The transfer must be valid, must have created the note being audited and must have
+        // spent from the tree this audit names
+        assert!(transfer_valid);
+        assert_eq!(out_commitment, note_commitment);
+        assert_eq!(transfer_root, merkle_root);
+        (image_id, out_nullifier, out_commitment)
+    });
+
+    // Optional head of the auditor's hash-chained audit log, committed as-is so the proof
+    // pins down exactly which log state it was produced against.
+    let audit_log_head: Option<Hash> = env::read();
+
     env::commit(&is_valid);
     env::commit(&merkle_root);
-} 
\ No newline at end of file
+    env::commit(&transfer_link);
+    env::commit(&audit_log_head);
+}
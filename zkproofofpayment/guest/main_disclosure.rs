@@ -0,0 +1,37 @@
+#![no_std]
+extern crate alloc;
+use alloc::string::String;
+use risc0_zkvm::guest::env;
+mod merkle;
+mod note;
+use merkle::Hash;
+use note::note_commitment;
+
+// Bits of the reveal mask, one per NotePayload field
+const REVEAL_AMOUNT: u8 = 1 << 0;
+const REVEAL_RECIPIENT: u8 = 1 << 1;
+const REVEAL_MEMO: u8 = 1 << 2;
+
+fn main() {
+    // Read inputs
+    let amount: u64 = env::read();           // private
+    let recipient: String = env::read();     // private
+    let memo: Option<String> = env::read();  // private
+    let blinding: Hash = env::read();        // private
+    let reveal_mask: u8 = env::read();       // public
+
+    // 1. Open the note commitment from the private note fields
+    let commitment = note_commitment(amount, &recipient, memo.as_deref(), blinding);
+
+    // 2. Keep only the fields the holder chose to reveal
+    let disclosed_amount = (reveal_mask & REVEAL_AMOUNT != 0).then_some(amount);
+    let disclosed_recipient = (reveal_mask & REVEAL_RECIPIENT != 0).then_some(recipient);
+    let disclosed_memo = (reveal_mask & REVEAL_MEMO != 0).then_some(memo);
+
+    // Commit the commitment, the mask and the chosen fields to the journal
+    env::commit(&commitment);
+    env::commit(&reveal_mask);
+    env::commit(&disclosed_amount);
+    env::commit(&disclosed_recipient);
+    env::commit(&disclosed_memo);
+}
@@ -0,0 +1,29 @@
+#![no_std]
+extern crate alloc;
+use alloc::vec::Vec;
+use risc0_zkvm::guest::env;
+mod merkle;
+mod rollup;
+use merkle::Hash;
+use rollup::{journal_leaf, rollup_root};
+
+fn main() {
+    // Read the image ID shared by every receipt in the batch
+    let image_id: [u32; 8] = env::read();
+    let count: u32 = env::read();
+
+    // 1. Verify each receipt (passed to the prover as an assumption) against its journal
+    let mut leaves: Vec<Hash> = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let journal: Vec<u8> = env::read();
+        env::verify(image_id, &journal).expect("batch receipt does not verify");
+        leaves.push(journal_leaf(&journal));
+    }
+
+    // 2. Commit to all journals with a single Merkle root
+    let root = rollup_root(&leaves);
+
+    env::commit(&image_id);
+    env::commit(&count);
+    env::commit(&root);
+}
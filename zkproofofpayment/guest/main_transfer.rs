@@ -1,49 +1,49 @@
 #![no_std]
+extern crate alloc;
+use alloc::string::String;
 use risc0_zkvm::guest::env;
 mod merkle;
+mod note;
 use merkle::{verify_merkle_proof, poseidon_hash2, Hash, DEPTH};
+use note::note_commitment;
 
 fn main() {
-    // Read inputs
+    // Read inputs: the spent note's opening, its secret and Merkle path
     let in_amount: u64 = env::read();
-    let in_nullifier: Hash = env::read();
+    let in_recipient: String = env::read();
+    let in_memo: Option<String> = env::read();
+    let in_blinding: Hash = env::read();
     let in_secret: Hash = env::read();
     let in_path_elements: [Hash; DEPTH] = env::read();
     let in_path_indices: [u8; DEPTH] = env::read();
-    let out_commitment: Hash = env::read();
     let merkle_root: Hash = env::read();
-    let recipient_pubkey: Hash = env::read();
+    // The created note's opening, for the same amount
+    let out_recipient: String = env::read();
+    let out_memo: Option<String> = env::read();
+    let out_blinding: Hash = env::read();
 
     // 1. Check in_amount > 0
     let is_amount_valid = in_amount > 0;
 
-    // 2. Compute in_commitment = Poseidon(in_amount, in_secret)
-    let in_amount_bytes = in_amount.to_be_bytes();
-    let in_commitment = poseidon_hash2_amount(in_amount_bytes, in_secret);
+    // 2. Open the spent note's commitment
+    let in_commitment = note_commitment(in_amount, &in_recipient, in_memo.as_deref(), in_blinding);
 
     // 3. Verify Merkle path for in_commitment → merkle_root
     let merkle_valid = verify_merkle_proof(in_commitment, merkle_root, in_path_elements, in_path_indices);
 
-    // 4. Compute out_nullifier = Poseidon(in_nullifier, in_secret)
-    let out_nullifier = poseidon_hash2(in_nullifier, in_secret);
+    // 4. Compute out_nullifier = Poseidon(in_commitment, in_secret), as main_nullifier does
+    let out_nullifier = poseidon_hash2(in_commitment, in_secret);
 
-    // 5. Compute out_commitment = Poseidon(in_amount, recipient_pubkey)
-    let out_commitment_calc = poseidon_hash2_amount(in_amount_bytes, recipient_pubkey);
+    // 5. Commit to the created note the same way, so disclosure and audit proofs can open it
+    let out_commitment = note_commitment(in_amount, &out_recipient, out_memo.as_deref(), out_blinding);
 
-    // 6. Assert out_commitment matches public input
-    let commitment_valid = out_commitment == out_commitment_calc;
+    // 6. Set final validity
+    let is_valid = is_amount_valid && merkle_valid;
 
-    // 7. Set final validity
-    let is_valid = is_amount_valid && merkle_valid && commitment_valid;
-
-    // Commit out_nullifier and validity to the journal
+    // Commit out_nullifier and validity to the journal, then the output note and root so
+    // that proofs composed on top of this receipt can refer to the transferred note
     env::commit(&out_nullifier);
     env::commit(&is_valid);
+    env::commit(&out_commitment);
+    env::commit(&merkle_root);
 }
-
-// Helper for Poseidon hash of (u64, Hash)
-fn poseidon_hash2_amount(a: [u8; 8], b: [u8; 32]) -> [u8; 32] {
-    let mut a_padded = [0u8; 32];
-    a_padded[24..].copy_from_slice(&a);
-    poseidon_hash2(a_padded, b)
-} 
\ No newline at end of file
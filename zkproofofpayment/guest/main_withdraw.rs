@@ -1,12 +1,18 @@
 #![no_std]
+extern crate alloc;
+use alloc::string::String;
 use risc0_zkvm::guest::env;
 mod merkle;
+mod note;
 use merkle::{verify_merkle_proof, poseidon_hash2, Hash, DEPTH};
+use note::note_commitment;
 
 fn main() {
     // Read inputs
     let in_amount: u64 = env::read();
-    let in_nullifier: Hash = env::read();
+    let in_recipient: String = env::read();
+    let in_memo: Option<String> = env::read();
+    let in_blinding: Hash = env::read();
     let in_secret: Hash = env::read();
     let in_path_elements: [Hash; DEPTH] = env::read();
     let in_path_indices: [u8; DEPTH] = env::read();
@@ -20,15 +26,14 @@ fn main() {
     // 2. withdrawal_amount == in_amount
     let is_withdrawal_valid = withdrawal_amount == in_amount;
 
-    // 3. Compute in_commitment = Poseidon(in_amount, in_secret)
-    let in_amount_bytes = in_amount.to_be_bytes();
-    let in_commitment = poseidon_hash2_amount(in_amount_bytes, in_secret);
+    // 3. Open the spent note's commitment
+    let in_commitment = note_commitment(in_amount, &in_recipient, in_memo.as_deref(), in_blinding);
 
     // 4. Verify Merkle path for in_commitment → merkle_root
     let merkle_valid = verify_merkle_proof(in_commitment, merkle_root, in_path_elements, in_path_indices);
 
-    // 5. Compute out_nullifier = Poseidon(in_nullifier, in_secret)
-    let out_nullifier = poseidon_hash2(in_nullifier, in_secret);
+    // 5. Compute out_nullifier = Poseidon(in_commitment, in_secret), as main_nullifier does
+    let out_nullifier = poseidon_hash2(in_commitment, in_secret);
 
     // 6. Check recipient_address is not zero
     let is_recipient_valid = recipient_address != [0u8; 32];
@@ -40,10 +45,3 @@ fn main() {
     env::commit(&out_nullifier);
     env::commit(&is_valid);
 }
-
-// Helper for Poseidon hash of (u64, Hash)
-fn poseidon_hash2_amount(a: [u8; 8], b: [u8; 32]) -> [u8; 32] {
-    let mut a_padded = [0u8; 32];
-    a_padded[24..].copy_from_slice(&a);
-    poseidon_hash2(a_padded, b)
-} 
\ No newline at end of file
@@ -1,5 +1,6 @@
 #![no_std]
-use poseidon::{Poseidon, Fr};
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
 
 pub const DEPTH: usize = 32;
 pub type Hash = [u8; 32];
@@ -18,10 +19,6 @@ pub fn verify_merkle_proof(leaf: Hash, root: Hash, path_elements: [Hash; DEPTH],
 }
 
 pub fn poseidon_hash2(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
-    let a_fr = Fr::from_bytes(&a).unwrap();
-    let b_fr = Fr::from_bytes(&b).unwrap();
-    let mut poseidon = Poseidon::new();
-    poseidon.update(&[a_fr, b_fr]);
-    let hash_fr = poseidon.finalize();
-    hash_fr.to_bytes()
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+    poseidon.hash_bytes_be(&[&a, &b]).unwrap()
 } 
\ No newline at end of file
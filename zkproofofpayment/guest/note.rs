@@ -0,0 +1,31 @@
+#![no_std]
+use risc0_zkvm::sha::{Impl, Sha256};
+use crate::merkle::{poseidon_hash2, Hash};
+
+// Map arbitrary bytes into the field: SHA-256 with the top byte cleared
+pub fn field_hash(data: &[u8]) -> Hash {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(Impl::hash_bytes(data).as_bytes());
+    out[0] = 0;
+    out
+}
+
+// Note commitment = Poseidon(Poseidon(Poseidon(amount, H(recipient)), H(memo)), blinding)
+// An absent memo hashes to zero so it cannot collide with an empty memo.
+pub fn note_commitment(amount: u64, recipient: &str, memo: Option<&str>, blinding: Hash) -> Hash {
+    let mut amount_padded = [0u8; 32];
+    amount_padded[24..].copy_from_slice(&amount.to_be_bytes());
+    let recipient_hash = field_hash(recipient.as_bytes());
+    let memo_hash = memo.map_or([0u8; 32], |m| field_hash(m.as_bytes()));
+    let inner = poseidon_hash2(amount_padded, recipient_hash);
+    let inner = poseidon_hash2(inner, memo_hash);
+    poseidon_hash2(inner, blinding)
+}
+
+// Tree leaf for a note: Poseidon(commitment, timestamp), so the time a note was
+// created is bound to the note and cannot be chosen by the prover
+pub fn timestamped_leaf(commitment: Hash, timestamp: u64) -> Hash {
+    let mut timestamp_padded = [0u8; 32];
+    timestamp_padded[24..].copy_from_slice(&timestamp.to_be_bytes());
+    poseidon_hash2(commitment, timestamp_padded)
+}
@@ -0,0 +1,42 @@
+use alloc::vec::Vec;
+use risc0_zkvm::sha::{Impl, Sha256};
+use crate::merkle::Hash;
+
+// Domain separation between leaves and inner nodes
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_prefixed(prefix: u8, parts: &[&[u8]]) -> Hash {
+    let mut data = Vec::new();
+    data.push(prefix);
+    for part in parts {
+        data.extend_from_slice(part);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(Impl::hash_bytes(&data).as_bytes());
+    out
+}
+
+// Leaf of the rollup tree for one receipt journal
+pub fn journal_leaf(journal: &[u8]) -> Hash {
+    hash_prefixed(LEAF_PREFIX, &[journal])
+}
+
+// SHA-256 Merkle root; an odd node at the end of a level is carried up unchanged
+pub fn rollup_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level: Vec<Hash> = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_prefixed(NODE_PREFIX, &[left, right]),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
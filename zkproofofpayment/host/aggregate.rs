@@ -0,0 +1,244 @@
+use crate::merkle::{timestamped_leaf, NoteTree};
+use crate::test_vectors::DEPTH;
+use crate::utils::{hex32, Hash};
+use risc0_zkvm::{ExecutorEnvBuilder, Journal};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use zkscanner::{note_commitment, NotePayload};
+
+/// A note as recorded by whoever maintains the note tree: its opening and creation time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeNote {
+    pub amount: u64,
+    pub recipient: String,
+    #[serde(default)]
+    pub memo: Option<String>,
+    #[serde(with = "hex32")]
+    pub blinding: Hash,
+    pub timestamp: u64,
+}
+
+impl TreeNote {
+    /// The note's tree leaf: its commitment, as the scanner and every guest compute it, bound
+    /// to its timestamp. Fails when the blinding is not a field element.
+    pub fn leaf(&self) -> Result<Hash, Box<dyn Error>> {
+        let payload = NotePayload {
+            amount: self.amount,
+            recipient: self.recipient.clone(),
+            memo: self.memo.clone(),
+            blinding: Some(self.blinding),
+            ..NotePayload::default()
+        };
+        let commitment = note_commitment(&payload)
+            .ok_or_else(|| format!("blinding 0x{} is not a field element", hex::encode(self.blinding)))?;
+        Ok(timestamped_leaf(commitment, self.timestamp))
+    }
+}
+
+/// Private opening of one note in the tree, with its Merkle path.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteOpening {
+    pub amount: u64,
+    pub recipient: String,
+    pub memo: Option<String>,
+    #[serde(with = "hex32")]
+    pub blinding: Hash,
+    /// Unix timestamp bound into the note's tree leaf.
+    pub timestamp: u64,
+    #[serde(with = "hex32::array")]
+    pub path_elements: [Hash; DEPTH],
+    pub path_indices: [u8; DEPTH],
+}
+
+/// Which payments to add up: those to `recipient` in `[start_time, end_time)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentFilter {
+    pub recipient: String,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+impl PaymentFilter {
+    /// Whether `main_aggregate` counts this note.
+    pub fn matches(&self, note: &NoteOpening) -> bool {
+        note.recipient == self.recipient && note.timestamp >= self.start_time && note.timestamp < self.end_time
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateInput {
+    #[serde(with = "hex32")]
+    pub merkle_root: Hash,
+    #[serde(flatten)]
+    pub filter: PaymentFilter,
+    pub notes: Vec<NoteOpening>,
+}
+
+impl AggregateInput {
+    /// Build the note tree over `notes`, in the order they were added, and open every note
+    /// `filter` matches. This is the tree `main_aggregate` checks paths against; its root must
+    /// be the one the verifier trusts.
+    pub fn from_notes(notes: &[TreeNote], filter: PaymentFilter) -> Result<Self, Box<dyn Error>> {
+        let tree = NoteTree::new(notes.iter().map(TreeNote::leaf).collect::<Result<_, _>>()?);
+        let notes = notes
+            .iter()
+            .enumerate()
+            .map(|(index, note)| {
+                let (path_elements, path_indices) = tree.path(index).expect("one leaf per note");
+                NoteOpening {
+                    amount: note.amount,
+                    recipient: note.recipient.clone(),
+                    memo: note.memo.clone(),
+                    blinding: note.blinding,
+                    timestamp: note.timestamp,
+                    path_elements,
+                    path_indices,
+                }
+            })
+            .filter(|opening| filter.matches(opening))
+            .collect();
+        Ok(AggregateInput { merkle_root: tree.root(), filter, notes })
+    }
+
+    /// Write the inputs in the order `main_aggregate` reads them.
+    pub fn write_env(&self, builder: &mut ExecutorEnvBuilder<'_>) -> Result<(), Box<dyn Error>> {
+        if self.filter.start_time >= self.filter.end_time {
+            return Err("aggregate filter has an empty time range".into());
+        }
+        builder
+            .write(&self.merkle_root)?
+            .write(&self.filter.recipient)?
+            .write(&self.filter.start_time)?
+            .write(&self.filter.end_time)?
+            .write(&(self.notes.len() as u32))?;
+        for note in &self.notes {
+            builder
+                .write(&note.amount)?
+                .write(&note.recipient)?
+                .write(&note.memo)?
+                .write(&note.blinding)?
+                .write(&note.timestamp)?
+                .write(&note.path_elements)?
+                .write(&note.path_indices)?;
+        }
+        Ok(())
+    }
+
+    /// The `min_total` the guest commits for this input.
+    pub fn min_total(&self) -> Option<u64> {
+        self.notes
+            .iter()
+            .filter(|note| self.filter.matches(note))
+            .try_fold(0u64, |total, note| total.checked_add(note.amount))
+    }
+}
+
+/// Public outputs of an aggregate proof.
+///
+/// `min_total` is the sum over the distinct matching notes the prover chose to open. It backs
+/// "paid at least X" claims only: nothing forces the prover to open every matching note under
+/// `merkle_root`, so it says nothing about an upper bound.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentTotal {
+    #[serde(with = "hex32")]
+    pub merkle_root: Hash,
+    #[serde(flatten)]
+    pub filter: PaymentFilter,
+    pub min_total: u64,
+}
+
+impl PaymentTotal {
+    pub fn from_journal(journal: &Journal) -> Result<Self, Box<dyn Error>> {
+        let (merkle_root, recipient, start_time, end_time, min_total) = journal.decode()?;
+        Ok(PaymentTotal {
+            merkle_root,
+            filter: PaymentFilter { recipient, start_time, end_time },
+            min_total,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::path_root;
+    use crate::utils::hex_to_bytes32;
+    use risc0_zkvm::ExecutorEnv;
+
+    fn opening(amount: u64, recipient: &str, timestamp: u64) -> NoteOpening {
+        NoteOpening {
+            amount,
+            recipient: recipient.to_string(),
+            memo: None,
+            blinding: [0u8; 32],
+            timestamp,
+            path_elements: [[0u8; 32]; DEPTH],
+            path_indices: [0u8; DEPTH],
+        }
+    }
+
+    fn filter(start_time: u64, end_time: u64) -> PaymentFilter {
+        PaymentFilter { recipient: "vendor-x".to_string(), start_time, end_time }
+    }
+
+    #[test]
+    fn test_filter_bounds_and_min_total() {
+        let input = AggregateInput {
+            merkle_root: [1u8; 32],
+            filter: filter(100, 200),
+            notes: vec![
+                opening(10, "vendor-x", 100),
+                opening(20, "vendor-x", 199),
+                opening(40, "vendor-x", 200),
+                opening(80, "vendor-y", 150),
+                opening(160, "vendor-x", 99),
+            ],
+        };
+        let matched: Vec<bool> = input.notes.iter().map(|n| input.filter.matches(n)).collect();
+        assert_eq!(matched, vec![true, true, false, false, false]);
+        assert_eq!(input.min_total(), Some(30));
+
+        let overflowing = AggregateInput {
+            notes: vec![opening(u64::MAX, "vendor-x", 150), opening(1, "vendor-x", 150)],
+            ..input
+        };
+        assert_eq!(overflowing.min_total(), None);
+        let empty = AggregateInput { filter: filter(200, 200), ..overflowing };
+        assert!(empty.write_env(&mut ExecutorEnv::builder()).is_err());
+    }
+
+    #[test]
+    fn test_from_notes_opens_matching_notes_in_one_tree() {
+        let note = |amount, recipient: &str, timestamp| TreeNote {
+            amount,
+            recipient: recipient.to_string(),
+            memo: None,
+            blinding: hex_to_bytes32(&format!("0x{:02x}", amount)),
+            timestamp,
+        };
+        let notes = vec![note(10, "vendor-x", 150), note(20, "vendor-y", 150), note(40, "vendor-x", 250)];
+        let input = AggregateInput::from_notes(&notes, filter(100, 200)).unwrap();
+        assert_eq!(input.min_total(), Some(10));
+        assert_eq!(input.notes.len(), 1);
+        let opened = &input.notes[0];
+        let leaf = notes[0].leaf().unwrap();
+        assert_eq!(path_root(leaf, &opened.path_elements, &opened.path_indices), input.merkle_root);
+        // The root covers every note, not only the opened ones.
+        assert_eq!(AggregateInput::from_notes(&notes, filter(200, 300)).unwrap().merkle_root, input.merkle_root);
+        let unreduced = TreeNote { blinding: [0xff; 32], ..notes[0].clone() };
+        assert!(AggregateInput::from_notes(&[unreduced], filter(100, 200)).is_err());
+    }
+
+    #[test]
+    fn test_journal_decodes_to_payment_total() {
+        // Committed in the same order as main_aggregate.
+        let committed = ([9u8; 32], "vendor-x".to_string(), 100u64, 200u64, 30u64);
+        let words = risc0_zkvm::serde::to_vec(&committed).unwrap();
+        let journal = Journal::new(words.iter().flat_map(|w| w.to_le_bytes()).collect());
+        let total = PaymentTotal::from_journal(&journal).unwrap();
+        assert_eq!(total, PaymentTotal { merkle_root: [9u8; 32], filter: filter(100, 200), min_total: 30 });
+        let json = serde_json::to_value(&total).unwrap();
+        assert_eq!(json["min_total"], 30);
+        assert_eq!(json["recipient"], "vendor-x");
+    }
+}
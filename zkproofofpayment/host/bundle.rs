@@ -0,0 +1,122 @@
+use crate::circuits::Circuit;
+use crate::prover::{Guest, ReceiptKind};
+use crate::rollup::{payment_image_id, RollupSummary};
+use risc0_zkvm::{sha::Digest, Receipt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Current on-disk bundle format version.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// A receipt together with everything an auditor needs to check it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProofBundle {
+    pub version: u32,
+    pub circuit: Circuit,
+    /// Image ID of the guest that produced the receipt.
+    pub image_id: Digest,
+    /// Unix timestamp at which the proof was generated.
+    pub created_at: i64,
+    /// Decoded journal, kept alongside the receipt for human inspection.
+    pub public_outputs: Value,
+    /// Kind of receipt held in `receipt`. Bundles written before this field existed are composite.
+    #[serde(default)]
+    pub receipt_kind: ReceiptKind,
+    pub receipt: Receipt,
+}
+
+impl ProofBundle {
+    pub fn new(circuit: Circuit, image_id: Digest, receipt: Receipt) -> Result<Self, Box<dyn Error>> {
+        let public_outputs = circuit.decode_journal(&receipt.journal)?;
+        let receipt_kind = ReceiptKind::of(&receipt)?;
+        Ok(ProofBundle {
+            version: BUNDLE_VERSION,
+            circuit,
+            image_id,
+            created_at: chrono::Utc::now().timestamp(),
+            public_outputs,
+            receipt_kind,
+            receipt,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bundle: ProofBundle = serde_json::from_slice(&fs::read(path)?)?;
+        if bundle.version != BUNDLE_VERSION {
+            return Err(format!("unsupported bundle version {}", bundle.version).into());
+        }
+        Ok(bundle)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Default output location: `<dir>/<circuit>-<created_at>.json`.
+    pub fn default_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}-{}.json", self.circuit.guest_name(), self.created_at))
+    }
+
+    /// Check the receipt against the image ID of the local guest for the bundle's circuit and
+    /// against the recorded public outputs.
+    ///
+    /// The image ID recorded in the bundle is only compared with the local one, never trusted:
+    /// whoever wrote the bundle chose it. Composite, succinct and Groth16 receipts are all
+    /// accepted, but the receipt must be of the kind the bundle claims to hold, and a journal
+    /// that reports `is_valid: false` is an error. The audit guest takes the image ID of a linked
+    /// transfer from the prover, so an audit bundle with a transfer link is only accepted if that
+    /// image ID is the one of the transfer guest in `guest_dir`; likewise a rollup bundle must
+    /// batch receipts of the local payment guest.
+    pub fn verify(&self, guest_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let actual = ReceiptKind::of(&self.receipt)?;
+        if actual != self.receipt_kind {
+            return Err(format!(
+                "bundle claims a {:?} receipt but holds a {:?} receipt",
+                self.receipt_kind, actual
+            )
+            .into());
+        }
+        let image_id = Guest::load(guest_dir, self.circuit)?.image_id;
+        if self.image_id != image_id {
+            return Err(format!(
+                "bundle records image {}, not the {} guest {}",
+                self.image_id,
+                self.circuit.guest_name(),
+                image_id
+            )
+            .into());
+        }
+        self.receipt.verify(image_id)?;
+        let decoded = self.circuit.decode_journal(&self.receipt.journal)?;
+        if decoded != self.public_outputs {
+            return Err("bundle public outputs do not match the receipt journal".into());
+        }
+        if decoded["is_valid"] == false {
+            return Err(format!("{} proof reports is_valid = false", self.circuit.label()).into());
+        }
+        if self.circuit == Circuit::Audit {
+            if let Some(linked) = decoded["transfer"]["image_id"].as_str() {
+                let transfer = Guest::load(guest_dir, Circuit::Transfer)?.image_id.to_string();
+                if linked != transfer {
+                    let msg = format!("audit proof is linked to image {}, not the transfer guest {}", linked, transfer);
+                    return Err(msg.into());
+                }
+            }
+        }
+        if self.circuit == Circuit::Rollup {
+            let batched = RollupSummary::from_journal(&self.receipt.journal)?.image_id;
+            let payment = payment_image_id(guest_dir)?;
+            if batched != payment {
+                return Err(format!("rollup batches image {}, not the payment guest {}", batched, payment).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+use crate::aggregate::{AggregateInput, PaymentTotal};
+use crate::bundle::ProofBundle;
+use crate::disclosure::{DisclosedFields, DisclosureInput};
+use crate::rollup::{RollupInput, RollupSummary};
+use crate::test_vectors::DEPTH;
+use crate::utils::{hex32, Hash};
+use clap::ValueEnum;
+use risc0_zkvm::{sha::Digest, ExecutorEnv, ExecutorEnvBuilder, Journal};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Guest programs the host knows how to drive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Circuit {
+    Merkle,
+    Audit,
+    Transfer,
+    Withdraw,
+    Nullifier,
+    Zkcondition,
+    Zksplit,
+    Zkstream,
+    Disclosure,
+    Aggregate,
+    Rollup,
+}
+
+impl Circuit {
+    /// Name of the guest binary under the guest directory.
+    pub fn guest_name(self) -> &'static str {
+        match self {
+            Circuit::Merkle => "main_merkle",
+            Circuit::Audit => "main_audit",
+            Circuit::Transfer => "main_transfer",
+            Circuit::Withdraw => "main_withdraw",
+            Circuit::Nullifier => "main_nullifier",
+            Circuit::Zkcondition => "main_zkcondition",
+            Circuit::Zksplit => "main_zksplit",
+            Circuit::Zkstream => "main_zkstream",
+            Circuit::Disclosure => "main_disclosure",
+            Circuit::Aggregate => "main_aggregate",
+            Circuit::Rollup => "main_rollup",
+        }
+    }
+
+    /// Label used in console output.
+    pub fn label(self) -> &'static str {
+        match self {
+            Circuit::Merkle => "Merkle",
+            Circuit::Audit => "Audit",
+            Circuit::Transfer => "Transfer",
+            Circuit::Withdraw => "Withdraw",
+            Circuit::Nullifier => "Nullifier",
+            Circuit::Zkcondition => "ZkCondition",
+            Circuit::Zksplit => "ZkSplit",
+            Circuit::Zkstream => "ZkStream",
+            Circuit::Disclosure => "Disclosure",
+            Circuit::Aggregate => "Aggregate",
+            Circuit::Rollup => "Rollup",
+        }
+    }
+
+    /// Build the executor environment for this circuit from a parsed input document.
+    ///
+    /// Inputs are written in exactly the order the guest reads them. Bundles the input refers to
+    /// are verified against the guests in `guest_dir` before their receipts become assumptions.
+    pub fn executor_env(self, guest_dir: &Path, input: Value) -> Result<ExecutorEnv<'static>, Box<dyn Error>> {
+        let mut builder = ExecutorEnv::builder();
+        match self {
+            Circuit::Merkle => {
+                let i: MerkleInput = serde_json::from_value(input)?;
+                builder
+                    .write(&i.root)?
+                    .write(&i.leaf)?
+                    .write(&i.path_elements)?
+                    .write(&i.path_indices)?;
+            }
+            Circuit::Audit => {
+                let i: AuditInput = serde_json::from_value(input)?;
+                builder
+                    .write(&i.note_commitment)?
+                    .write(&i.view_key)?
+                    .write(&i.amount)?
+                    .write(&i.timestamp)?
+                    .write(&i.purpose)?
+                    .write(&i.audit_id)?
+                    .write(&i.merkle_root)?;
+                match &i.transfer_bundle {
+                    Some(path) => {
+                        let transfer = ProofBundle::load(path)?;
+                        if transfer.circuit != Circuit::Transfer {
+                            return Err(format!("{} is not a transfer bundle", path.display()).into());
+                        }
+                        transfer.verify(guest_dir)?;
+                        let image_id: [u32; 8] = transfer.image_id.into();
+                        builder
+                            .write(&Some(image_id))?
+                            .write(&transfer.receipt.journal.bytes)?;
+                        builder.add_assumption(transfer.receipt);
+                    }
+                    None => {
+                        builder.write(&None::<[u32; 8]>)?;
+                    }
+                }
+                builder.write(&i.audit_log_head)?;
+            }
+            Circuit::Transfer => {
+                let i: TransferInput = serde_json::from_value(input)?;
+                i.spent.write_env(&mut builder)?;
+                builder
+                    .write(&i.merkle_root)?
+                    .write(&i.out_recipient)?
+                    .write(&i.out_memo)?
+                    .write(&i.out_blinding)?;
+            }
+            Circuit::Withdraw => {
+                let i: WithdrawInput = serde_json::from_value(input)?;
+                i.spent.write_env(&mut builder)?;
+                builder
+                    .write(&i.merkle_root)?
+                    .write(&i.recipient_address)?
+                    .write(&i.withdrawal_amount)?;
+            }
+            Circuit::Nullifier => {
+                let i: NullifierInput = serde_json::from_value(input)?;
+                builder.write(&i.note_commitment)?.write(&i.secret)?;
+            }
+            Circuit::Zkcondition => {
+                let i: ZkConditionInput = serde_json::from_value(input)?;
+                builder.write(&i.condition_type)?.write(&i.value)?;
+            }
+            Circuit::Zksplit => {
+                let i: ZkSplitInput = serde_json::from_value(input)?;
+                builder.write(&i.recipients)?.write(&i.amounts)?;
+            }
+            Circuit::Zkstream => {
+                let i: ZkStreamInput = serde_json::from_value(input)?;
+                builder.write(&i.stream_id)?.write(&i.total_amount)?;
+            }
+            Circuit::Disclosure => {
+                let i: DisclosureInput = serde_json::from_value(input)?;
+                i.write_env(&mut builder)?;
+            }
+            Circuit::Aggregate => {
+                let i: AggregateInput = serde_json::from_value(input)?;
+                i.write_env(&mut builder)?;
+            }
+            Circuit::Rollup => {
+                let i: RollupInput = serde_json::from_value(input)?;
+                i.write_env(guest_dir, &mut builder)?;
+            }
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Decode the journal committed by this circuit into named public outputs.
+    pub fn decode_journal(self, journal: &Journal) -> Result<Value, Box<dyn Error>> {
+        let outputs = match self {
+            Circuit::Merkle => {
+                let root: Hash = journal.decode()?;
+                json!({ "root": hex_hash(&root) })
+            }
+            Circuit::Audit => {
+                let decoded: (bool, Hash, Option<TransferLink>, Option<Hash>) = journal.decode()?;
+                let (is_valid, merkle_root, transfer, audit_log_head) = decoded;
+                let transfer = transfer.map(|(image_id, out_nullifier, out_commitment)| {
+                    json!({
+                        "image_id": Digest::from(image_id).to_string(),
+                        "out_nullifier": hex_hash(&out_nullifier),
+                        "out_commitment": hex_hash(&out_commitment),
+                    })
+                });
+                json!({
+                    "is_valid": is_valid,
+                    "merkle_root": hex_hash(&merkle_root),
+                    "transfer": transfer,
+                    "audit_log_head": audit_log_head.map(|h| hex_hash(&h)),
+                })
+            }
+            Circuit::Transfer => {
+                let (out_nullifier, is_valid, out_commitment, merkle_root): (Hash, bool, Hash, Hash) =
+                    journal.decode()?;
+                json!({
+                    "out_nullifier": hex_hash(&out_nullifier),
+                    "is_valid": is_valid,
+                    "out_commitment": hex_hash(&out_commitment),
+                    "merkle_root": hex_hash(&merkle_root),
+                })
+            }
+            Circuit::Withdraw => {
+                let (out_nullifier, is_valid): (Hash, bool) = journal.decode()?;
+                json!({ "out_nullifier": hex_hash(&out_nullifier), "is_valid": is_valid })
+            }
+            Circuit::Nullifier => {
+                let (nullifier, is_valid): (Hash, bool) = journal.decode()?;
+                json!({ "nullifier": hex_hash(&nullifier), "is_valid": is_valid })
+            }
+            Circuit::Zkcondition | Circuit::Zksplit | Circuit::Zkstream => {
+                let is_valid: bool = journal.decode()?;
+                json!({ "is_valid": is_valid })
+            }
+            Circuit::Disclosure => serde_json::to_value(DisclosedFields::from_journal(journal)?)?,
+            Circuit::Aggregate => serde_json::to_value(PaymentTotal::from_journal(journal)?)?,
+            Circuit::Rollup => serde_json::to_value(RollupSummary::from_journal(journal)?)?,
+        };
+        Ok(outputs)
+    }
+}
+
+/// Image ID, nullifier and output commitment of the transfer an audit proof is linked to.
+type TransferLink = ([u32; 8], Hash, Hash);
+
+fn hex_hash(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MerkleInput {
+    #[serde(with = "hex32")]
+    pub root: Hash,
+    #[serde(with = "hex32")]
+    pub leaf: Hash,
+    #[serde(with = "hex32::array")]
+    pub path_elements: [Hash; DEPTH],
+    pub path_indices: [u8; DEPTH],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditInput {
+    #[serde(with = "hex32")]
+    pub note_commitment: Hash,
+    #[serde(with = "hex32")]
+    pub view_key: Hash,
+    pub amount: u64,
+    pub timestamp: u64,
+    pub purpose: u64,
+    #[serde(with = "hex32")]
+    pub audit_id: Hash,
+    #[serde(with = "hex32")]
+    pub merkle_root: Hash,
+    /// Transfer bundle to compose with. Its receipt is added as an assumption and the audit
+    /// guest checks that the transfer created `note_commitment`.
+    #[serde(default)]
+    pub transfer_bundle: Option<PathBuf>,
+    /// Head of a verified hash-chained audit log (`zkaudit verify-audit-log`), committed to the
+    /// journal so the proof names the log state it belongs to.
+    #[serde(default, with = "hex32::option")]
+    pub audit_log_head: Option<Hash>,
+}
+
+/// Opening of the note a transfer or withdrawal spends, with its secret and Merkle path. The
+/// guests commit to it with the same note commitment as disclosure, aggregate and nullifier
+/// proofs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpentNote {
+    pub in_amount: u64,
+    pub in_recipient: String,
+    #[serde(default)]
+    pub in_memo: Option<String>,
+    #[serde(with = "hex32")]
+    pub in_blinding: Hash,
+    #[serde(with = "hex32")]
+    pub in_secret: Hash,
+    #[serde(with = "hex32::array")]
+    pub in_path_elements: [Hash; DEPTH],
+    pub in_path_indices: [u8; DEPTH],
+}
+
+impl SpentNote {
+    fn write_env(&self, builder: &mut ExecutorEnvBuilder<'_>) -> Result<(), Box<dyn Error>> {
+        builder
+            .write(&self.in_amount)?
+            .write(&self.in_recipient)?
+            .write(&self.in_memo)?
+            .write(&self.in_blinding)?
+            .write(&self.in_secret)?
+            .write(&self.in_path_elements)?
+            .write(&self.in_path_indices)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferInput {
+    #[serde(flatten)]
+    pub spent: SpentNote,
+    #[serde(with = "hex32")]
+    pub merkle_root: Hash,
+    /// Opening of the created note, which carries the spent note's amount.
+    pub out_recipient: String,
+    #[serde(default)]
+    pub out_memo: Option<String>,
+    #[serde(with = "hex32")]
+    pub out_blinding: Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WithdrawInput {
+    #[serde(flatten)]
+    pub spent: SpentNote,
+    #[serde(with = "hex32")]
+    pub merkle_root: Hash,
+    #[serde(with = "hex32")]
+    pub recipient_address: Hash,
+    pub withdrawal_amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NullifierInput {
+    #[serde(with = "hex32")]
+    pub note_commitment: Hash,
+    #[serde(with = "hex32")]
+    pub secret: Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkConditionInput {
+    pub condition_type: u8,
+    pub value: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkSplitInput {
+    #[serde(with = "hex32::array")]
+    pub recipients: [Hash; 2],
+    pub amounts: [u64; 2],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkStreamInput {
+    #[serde(with = "hex32")]
+    pub stream_id: Hash,
+    pub total_amount: u64,
+}
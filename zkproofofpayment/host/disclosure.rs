@@ -0,0 +1,158 @@
+use crate::bundle::ProofBundle;
+use crate::circuits::Circuit;
+use crate::utils::{hex32, Hash};
+use risc0_zkvm::{ExecutorEnvBuilder, Journal};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+use zkscanner::{note_commitment, NotePayload};
+
+/// A `NotePayload` field that can be revealed by a disclosure proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteField {
+    Amount,
+    Recipient,
+    Memo,
+}
+
+impl NoteField {
+    /// Bit of this field in the guest's reveal mask.
+    fn bit(self) -> u8 {
+        match self {
+            NoteField::Amount => 1 << 0,
+            NoteField::Recipient => 1 << 1,
+            NoteField::Memo => 1 << 2,
+        }
+    }
+}
+
+pub fn reveal_mask(fields: &[NoteField]) -> u8 {
+    fields.iter().fold(0, |mask, field| mask | field.bit())
+}
+
+/// Private note opening plus the fields the holder chose to reveal.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisclosureInput {
+    pub note: NotePayload,
+    /// Commitment randomness of the note.
+    #[serde(with = "hex32")]
+    pub blinding: Hash,
+    pub reveal: Vec<NoteField>,
+}
+
+impl DisclosureInput {
+    /// Write the inputs in the order `main_disclosure` reads them.
+    pub fn write_env(&self, builder: &mut ExecutorEnvBuilder<'_>) -> Result<(), Box<dyn Error>> {
+        builder
+            .write(&self.note.amount)?
+            .write(&self.note.recipient)?
+            .write(&self.note.memo)?
+            .write(&self.blinding)?
+            .write(&reveal_mask(&self.reveal))?;
+        Ok(())
+    }
+
+    /// What `main_disclosure` should commit for this input, computed on the host. Fails when the
+    /// blinding is not a field element.
+    pub fn expected_fields(&self) -> Result<DisclosedFields, Box<dyn Error>> {
+        let payload = NotePayload { blinding: Some(self.blinding), ..self.note.clone() };
+        let note_commitment = note_commitment(&payload).ok_or("blinding is not a field element")?;
+        let mask = reveal_mask(&self.reveal);
+        Ok(DisclosedFields {
+            note_commitment,
+            reveal_mask: mask,
+            amount: (mask & NoteField::Amount.bit() != 0).then_some(self.note.amount),
+            recipient: (mask & NoteField::Recipient.bit() != 0).then(|| self.note.recipient.clone()),
+            memo: (mask & NoteField::Memo.bit() != 0).then(|| self.note.memo.clone()),
+        })
+    }
+}
+
+/// Note fields revealed by a disclosure proof. Fields that were not chosen are `None`; a revealed
+/// memo is `Some(None)` when the note has no memo.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisclosedFields {
+    #[serde(with = "hex32")]
+    pub note_commitment: Hash,
+    pub reveal_mask: u8,
+    pub amount: Option<u64>,
+    pub recipient: Option<String>,
+    pub memo: Option<Option<String>>,
+}
+
+impl DisclosedFields {
+    pub fn from_journal(journal: &Journal) -> Result<Self, Box<dyn Error>> {
+        let (note_commitment, reveal_mask, amount, recipient, memo) = journal.decode()?;
+        Ok(DisclosedFields { note_commitment, reveal_mask, amount, recipient, memo })
+    }
+}
+
+/// A shareable disclosure: the proof bundle and the fields it attests to.
+#[derive(Debug)]
+pub struct DisclosurePackage {
+    pub fields: DisclosedFields,
+    pub bundle: ProofBundle,
+}
+
+impl DisclosurePackage {
+    pub fn from_bundle(bundle: ProofBundle) -> Result<Self, Box<dyn Error>> {
+        if bundle.circuit != Circuit::Disclosure {
+            return Err(format!("expected a disclosure bundle, got {:?}", bundle.circuit).into());
+        }
+        let fields = DisclosedFields::from_journal(&bundle.receipt.journal)?;
+        Ok(DisclosurePackage { fields, bundle })
+    }
+
+    /// Verify the receipt and return the revealed fields. The auditor still has to check that
+    /// `note_commitment` is a commitment they expect, e.g. a leaf of the on-chain note tree.
+    pub fn verify(&self, guest_dir: &Path) -> Result<&DisclosedFields, Box<dyn Error>> {
+        self.bundle.verify(guest_dir)?;
+        Ok(&self.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reveal_mask_matches_guest_bits() {
+        assert_eq!(reveal_mask(&[]), 0);
+        assert_eq!(reveal_mask(&[NoteField::Amount]), 0b001);
+        assert_eq!(reveal_mask(&[NoteField::Memo, NoteField::Amount]), 0b101);
+        assert_eq!(reveal_mask(&[NoteField::Amount, NoteField::Recipient, NoteField::Memo]), 0b111);
+    }
+
+    #[test]
+    fn test_journal_decodes_revealed_and_hidden_fields() {
+        // Committed in the same order as main_disclosure, with only the amount and memo revealed.
+        let commitment = [7u8; 32];
+        let committed = (commitment, 0b101u8, Some(1500u64), None::<String>, Some(None::<String>));
+        let words = risc0_zkvm::serde::to_vec(&committed).unwrap();
+        let journal = Journal::new(words.iter().flat_map(|w| w.to_le_bytes()).collect());
+
+        let fields = DisclosedFields::from_journal(&journal).unwrap();
+        assert_eq!(fields.note_commitment, commitment);
+        assert_eq!(fields.reveal_mask, 0b101);
+        assert_eq!(fields.amount, Some(1500));
+        assert_eq!(fields.recipient, None);
+        assert_eq!(fields.memo, Some(None));
+
+        let input = DisclosureInput {
+            note: NotePayload {
+                amount: 1500,
+                recipient: "bob".to_string(),
+                memo: Some("rent".to_string()),
+                ..NotePayload::default()
+            },
+            blinding: [1u8; 32],
+            reveal: vec![NoteField::Recipient],
+        };
+        assert!(input.expected_fields().is_err());
+        let input = DisclosureInput { blinding: [0u8; 32], ..input };
+        let expected = input.expected_fields().unwrap();
+        assert_eq!(expected.reveal_mask, 0b010);
+        assert_eq!((expected.amount, expected.recipient.as_deref(), expected.memo), (None, Some("bob"), None));
+    }
+}
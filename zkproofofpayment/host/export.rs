@@ -0,0 +1,166 @@
+use crate::bundle::ProofBundle;
+use crate::prover::ReceiptKind;
+use borsh::{BorshDeserialize, BorshSerialize};
+use clap::ValueEnum;
+use risc0_zkvm::sha::Digestible;
+use std::error::Error;
+
+/// `bytes4(keccak256("verify(bytes,bytes32,bytes32)"))` on the RISC Zero `IRiscZeroVerifier`.
+pub const VERIFY_SELECTOR: [u8; 4] = [0xab, 0x75, 0x0e, 0x75];
+
+/// Size of a RISC Zero Groth16 seal: the proof points a (G1), b (G2) and c (G1).
+pub const GROTH16_SEAL_LEN: usize = 256;
+
+/// Target encoding for on-chain verification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// ABI-encoded calldata for `IRiscZeroVerifier.verify(seal, imageId, journalDigest)`.
+    Solidity,
+    /// Borsh-encoded instruction data for a Solana verifier program.
+    Solana,
+}
+
+/// Everything an on-chain verifier needs from a Groth16 proof bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnchainProof {
+    /// First four bytes of the verifier parameters digest; routes to the matching verifier.
+    pub selector: [u8; 4],
+    pub seal: [u8; GROTH16_SEAL_LEN],
+    pub image_id: [u8; 32],
+    pub journal_digest: [u8; 32],
+}
+
+/// Borsh layout of [`OnchainProof`] for a Solana program.
+#[derive(Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct SolanaVerifyArgs {
+    pub selector: [u8; 4],
+    pub pi_a: [u8; 64],
+    pub pi_b: [u8; 128],
+    pub pi_c: [u8; 64],
+    pub image_id: [u8; 32],
+    pub journal_digest: [u8; 32],
+}
+
+impl OnchainProof {
+    /// Extract the Groth16 seal, image ID and journal digest from a bundle.
+    pub fn from_bundle(bundle: &ProofBundle) -> Result<Self, Box<dyn Error>> {
+        if bundle.receipt_kind != ReceiptKind::Groth16 {
+            return Err(format!(
+                "on-chain export needs a Groth16 receipt, bundle holds a {:?} receipt",
+                bundle.receipt_kind
+            )
+            .into());
+        }
+        let groth16 = bundle.receipt.inner.groth16()?;
+        let seal: [u8; GROTH16_SEAL_LEN] = groth16
+            .seal
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("unexpected Groth16 seal length {}", groth16.seal.len()))?;
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&groth16.verifier_parameters.as_bytes()[..4]);
+        Ok(OnchainProof {
+            selector,
+            seal,
+            image_id: bundle.image_id.into(),
+            journal_digest: bundle.receipt.journal.digest().into(),
+        })
+    }
+
+    /// Seal as expected by the RISC Zero verifier router: selector followed by the raw seal.
+    pub fn encoded_seal(&self) -> Vec<u8> {
+        [&self.selector[..], &self.seal[..]].concat()
+    }
+
+    /// ABI-encode a call to `verify(bytes seal, bytes32 imageId, bytes32 journalDigest)`.
+    pub fn solidity_calldata(&self) -> Vec<u8> {
+        let seal = self.encoded_seal();
+        let mut out = Vec::with_capacity(4 + 32 * 4 + seal.len().div_ceil(32) * 32);
+        out.extend_from_slice(&VERIFY_SELECTOR);
+        // Head: offset of the dynamic `seal` argument, then the two static words.
+        out.extend_from_slice(&abi_word(3 * 32));
+        out.extend_from_slice(&self.image_id);
+        out.extend_from_slice(&self.journal_digest);
+        // Tail: length-prefixed seal, right-padded to a whole word.
+        out.extend_from_slice(&abi_word(seal.len() as u64));
+        out.extend_from_slice(&seal);
+        out.resize(out.len() + (32 - seal.len() % 32) % 32, 0);
+        out
+    }
+
+    pub fn solana_args(&self) -> SolanaVerifyArgs {
+        let mut args = SolanaVerifyArgs {
+            selector: self.selector,
+            pi_a: [0u8; 64],
+            pi_b: [0u8; 128],
+            pi_c: [0u8; 64],
+            image_id: self.image_id,
+            journal_digest: self.journal_digest,
+        };
+        args.pi_a.copy_from_slice(&self.seal[..64]);
+        args.pi_b.copy_from_slice(&self.seal[64..192]);
+        args.pi_c.copy_from_slice(&self.seal[192..]);
+        args
+    }
+
+    pub fn solana_instruction_data(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(borsh::to_vec(&self.solana_args())?)
+    }
+
+    pub fn encode(&self, format: ExportFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            ExportFormat::Solidity => Ok(self.solidity_calldata()),
+            ExportFormat::Solana => self.solana_instruction_data(),
+        }
+    }
+}
+
+fn abi_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> OnchainProof {
+        let mut seal = [0u8; GROTH16_SEAL_LEN];
+        for (i, b) in seal.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        OnchainProof {
+            selector: [0xc1, 0x01, 0xb4, 0x2b],
+            seal,
+            image_id: [0x11; 32],
+            journal_digest: [0x22; 32],
+        }
+    }
+
+    fn golden(contents: &str) -> Vec<u8> {
+        hex::decode(contents.split_whitespace().collect::<String>()).unwrap()
+    }
+
+    // Produced outside this crate with alloy-sol-types 0.8: `verifyCall { seal, imageId,
+    // journalDigest }.abi_encode()` for `function verify(bytes seal, bytes32 imageId, bytes32
+    // journalDigest)`, with the fixture's encoded seal and hashes. The selector comes from
+    // alloy's own keccak256 of the signature.
+    #[test]
+    fn test_solidity_calldata_matches_golden() {
+        let expected = golden(include_str!("testdata/solidity_calldata.hex"));
+        assert_eq!(fixture().solidity_calldata(), expected);
+    }
+
+    // Produced by a short Python script from the Borsh spec rather than the borsh crate: fixed
+    // `[u8; N]` arrays are their raw bytes and struct fields follow in declaration order, so the
+    // data is selector, seal[..64], seal[64..192], seal[192..], image ID, journal digest.
+    #[test]
+    fn test_solana_borsh_matches_golden() {
+        let expected = golden(include_str!("testdata/solana_borsh.hex"));
+        let encoded = fixture().solana_instruction_data().unwrap();
+        assert_eq!(encoded, expected);
+        let decoded: SolanaVerifyArgs = borsh::from_slice(&encoded).unwrap();
+        assert_eq!(decoded, fixture().solana_args());
+    }
+}
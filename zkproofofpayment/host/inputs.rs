@@ -0,0 +1,21 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Read a JSON or TOML document, chosen by file extension (JSON when unknown).
+pub fn load_document(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => serde_json::to_value(toml::from_str::<toml::Value>(&text)?)?,
+        _ => serde_json::from_str(&text)?,
+    };
+    Ok(value)
+}
+
+/// Read a JSON or TOML document straight into a typed value.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn Error>> {
+    Ok(serde_json::from_value(load_document(path)?)?)
+}
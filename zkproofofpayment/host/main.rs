@@ -1,181 +1,293 @@
+mod aggregate;
+mod bundle;
+mod circuits;
+mod disclosure;
+mod export;
+mod inputs;
+mod merkle;
+mod prover;
+mod rollup;
+mod scan;
 mod test_vectors;
 mod utils;
-use test_vectors::*;
-use utils::*;
-use risc0_zkvm::{default_prover, ExecutorEnv, serde::to_vec};
+mod vectors;
+
+use aggregate::{AggregateInput, PaymentFilter, TreeNote};
+use bundle::ProofBundle;
+use circuits::Circuit;
+use disclosure::DisclosurePackage;
+use clap::{Parser, Subcommand};
+use export::{ExportFormat, OnchainProof};
+use prover::{Guest, ReceiptKind, DEFAULT_GUEST_DIR};
+use rollup::{InclusionProof, RollupInput, RollupSummary};
 use std::error::Error;
+use std::path::{Path, PathBuf};
+use utils::parse_bytes32;
+use zkscanner::ChainHead;
 
-const DEPTH: usize = 32;
+/// Default directory for proof bundles written by `prove`.
+const DEFAULT_BUNDLE_DIR: &str = "../../zkproofs";
 
-type Hash = [u8; 32];
+#[derive(Parser)]
+#[command(name = "zkaudit", about = "CipherPay zkAudit prover, verifier and note scanner")]
+struct Cli {
+    /// Directory containing the built guest binaries.
+    #[arg(long, global = true, default_value = DEFAULT_GUEST_DIR)]
+    guest_dir: PathBuf,
 
-fn hex_to_bytes32(hex: &str) -> Hash {
-    let hex = hex.trim_start_matches("0x");
-    let mut bytes = [0u8; 32];
-    let hex_bytes = hex::decode(hex).unwrap();
-    let offset = 32 - hex_bytes.len();
-    bytes[offset..].copy_from_slice(&hex_bytes);
-    bytes
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn run_merkle() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in merkle_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.root)?)
-            .add_input(&to_vec(&tv.leaf)?)
-            .add_input(&to_vec(&tv.path_elements)?)
-            .add_input(&to_vec(&tv.path_indices)?)
-            .build()?;
-        let guest_path = "../guest/main_merkle";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let computed_root: Hash = receipt.journal.decode()?;
-        println!("[Merkle][Test {}] Computed root in zkVM: 0x{}", i, hex::encode(computed_root));
-        receipt.verify(guest_path)?;
-        println!("[Merkle][Test {}] Proof verified!", i);
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Prove a circuit over inputs read from a JSON or TOML file and write a proof bundle.
+    Prove {
+        circuit: Circuit,
+        /// Input file (.json or .toml) with the circuit's private and public inputs.
+        #[arg(long)]
+        input: PathBuf,
+        /// Output bundle path. Defaults to `zkproofs/<circuit>-<timestamp>.json`.
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+        /// Receipt to produce: `groth16` is required for on-chain verification.
+        #[arg(long, value_enum, default_value_t = ReceiptKind::Composite)]
+        receipt_kind: ReceiptKind,
+    },
+    /// Verify a proof bundle.
+    Verify { bundle: PathBuf },
+    /// Print the circuit, image ID and public outputs of a proof bundle.
+    Inspect { bundle: PathBuf },
+    /// Export a Groth16 bundle as calldata for an on-chain verifier, written as hex.
+    Export {
+        bundle: PathBuf,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Solidity)]
+        format: ExportFormat,
+        /// Output file. Defaults to stdout.
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+    /// Build an inclusion proof for one bundle of a rollup batch file.
+    Inclusion {
+        /// Rollup input file listing the batch's bundles.
+        batch: PathBuf,
+        #[arg(long)]
+        index: usize,
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+    /// Verify a rollup bundle and check that an inclusion proof belongs to it.
+    VerifyInclusion { rollup: PathBuf, proof: PathBuf },
+    /// Verify a hash-chained audit log and print its head, for an audit input's `audit_log_head`.
+    VerifyAuditLog {
+        log: PathBuf,
+        /// Hex Ed25519 public key that signed the checkpoints.
+        #[arg(long)]
+        public_key: String,
+        /// Event count of a head verified before; the log must still reach it.
+        #[arg(long, requires = "known_head")]
+        known_events: Option<u64>,
+        /// Hex hash of the head verified before, printed by an earlier run.
+        #[arg(long, requires = "known_events")]
+        known_head: Option<String>,
+    },
+    /// Scan a wallet file for notes decryptable with its view keys.
+    Scan {
+        #[arg(long)]
+        wallet: PathBuf,
+        /// Only consider notes whose metadata contains this substring.
+        #[arg(long)]
+        filter: Option<String>,
+        /// Resume from this checkpoint file and update it, scanning only notes not seen before.
+        #[arg(long, conflicts_with = "filter")]
+        checkpoint: Option<PathBuf>,
+        /// Configuration file selecting the audit log backend [default: the repository's
+        /// config/default.toml, built in].
+        #[arg(long, env = "ZKAUDIT_CONFIG")]
+        config: Option<PathBuf>,
+        /// Print audit events to stderr instead of the configured audit log.
+        #[arg(long, short)]
+        verbose: bool,
+    },
+    /// Scan a wallet file's notes with a delegated auditor view key, within its window and scope.
+    AuditScan {
+        #[arg(long)]
+        wallet: PathBuf,
+        /// Signed delegation file from the wallet owner.
+        #[arg(long)]
+        delegation: PathBuf,
+        /// Payment address of the delegated epoch, as published by the wallet owner. It commits to
+        /// the key the delegation must be signed with.
+        #[arg(long)]
+        address: PathBuf,
+        /// Configuration file selecting the audit log backend [default: the repository's
+        /// config/default.toml, built in].
+        #[arg(long, env = "ZKAUDIT_CONFIG")]
+        config: Option<PathBuf>,
+        /// Print audit events to stderr instead of the configured audit log.
+        #[arg(long, short)]
+        verbose: bool,
+    },
+    /// Build the note tree over a list of notes and write an aggregate input opening those that
+    /// match the filter.
+    AggregateInput {
+        /// JSON list of every note in the tree, in the order they were added.
+        #[arg(long)]
+        notes: PathBuf,
+        #[arg(long)]
+        recipient: String,
+        /// Inclusive start of the time range, as a Unix timestamp.
+        #[arg(long)]
+        start_time: u64,
+        /// Exclusive end of the time range, as a Unix timestamp.
+        #[arg(long)]
+        end_time: u64,
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+    /// Prove and verify the built-in test vectors for a circuit.
+    Vectors { circuit: Circuit },
+}
+
+fn prove(
+    guest_dir: &Path,
+    circuit: Circuit,
+    input: &Path,
+    out: Option<PathBuf>,
+    kind: ReceiptKind,
+) -> Result<(), Box<dyn Error>> {
+    let guest = Guest::load(guest_dir, circuit)?;
+    let env = circuit.executor_env(guest_dir, inputs::load_document(input)?)?;
+    let receipt = guest.prove(env, kind)?;
+    let bundle = ProofBundle::new(circuit, guest.image_id, receipt)?;
+    let path = out.unwrap_or_else(|| bundle.default_path(Path::new(DEFAULT_BUNDLE_DIR)));
+    bundle.save(&path)?;
+    println!("[{}] Proof written to {}", circuit.label(), path.display());
     Ok(())
 }
 
-fn run_audit() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in audit_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.note_commitment)?)
-            .add_input(&to_vec(&tv.view_key)?)
-            .add_input(&to_vec(&tv.amount)?)
-            .add_input(&to_vec(&tv.timestamp)?)
-            .add_input(&to_vec(&tv.purpose)?)
-            .add_input(&to_vec(&tv.audit_id)?)
-            .add_input(&to_vec(&tv.merkle_root)?)
-            .build()?;
-        let guest_path = "../guest/main_audit";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let is_valid: bool = receipt.journal.decode()?;
-        let returned_merkle_root: Hash = receipt.journal.decode()?;
-        println!("[Audit][Test {}] Proof valid: {}", i, is_valid);
-        println!("[Audit][Test {}] Merkle root: 0x{}", i, hex::encode(returned_merkle_root));
-        receipt.verify(guest_path)?;
-        println!("[Audit][Test {}] Proof verified!", i);
+fn verify(guest_dir: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    let bundle = ProofBundle::load(path)?;
+    if bundle.circuit == Circuit::Disclosure {
+        let package = DisclosurePackage::from_bundle(bundle)?;
+        let fields = package.verify(guest_dir)?;
+        println!("[Disclosure] Proof verified for note 0x{}", hex::encode(fields.note_commitment));
+        println!("[Disclosure] Revealed: {}", serde_json::to_string_pretty(fields)?);
+        return Ok(());
     }
+    bundle.verify(guest_dir)?;
+    println!("[{}] {:?} proof verified!", bundle.circuit.label(), bundle.receipt_kind);
     Ok(())
 }
 
-fn run_transfer() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in transfer_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.amount)?)
-            .add_input(&to_vec(&tv.sender)?)
-            .add_input(&to_vec(&tv.receiver)?)
-            .build()?;
-        let guest_path = "../guest/main_transfer";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("Transfer", i, valid);
-        receipt.verify(guest_path)?;
-    }
+fn inspect(path: &Path) -> Result<(), Box<dyn Error>> {
+    let bundle = ProofBundle::load(path)?;
+    println!("circuit:    {}", bundle.circuit.guest_name());
+    println!("image_id:   {}", bundle.image_id);
+    println!("receipt:    {:?}", bundle.receipt_kind);
+    println!("created_at: {}", bundle.created_at);
+    println!("outputs:    {}", serde_json::to_string_pretty(&bundle.public_outputs)?);
     Ok(())
 }
 
-fn run_withdraw() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in withdraw_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.amount)?)
-            .add_input(&to_vec(&tv.recipient)?)
-            .build()?;
-        let guest_path = "../guest/main_withdraw";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("Withdraw", i, valid);
-        receipt.verify(guest_path)?;
+fn export(path: &Path, format: ExportFormat, out: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let bundle = ProofBundle::load(path)?;
+    let encoded = hex::encode(OnchainProof::from_bundle(&bundle)?.encode(format)?);
+    match out {
+        Some(out) => std::fs::write(&out, format!("0x{}\n", encoded))?,
+        None => println!("0x{}", encoded),
     }
     Ok(())
 }
 
-fn run_nullifier() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in nullifier_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.nullifier)?)
-            .add_input(&to_vec(&tv.secret)?)
-            .build()?;
-        let guest_path = "../guest/main_nullifier";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("Nullifier", i, valid);
-        receipt.verify(guest_path)?;
+fn inclusion(guest_dir: &Path, batch: &Path, index: usize, out: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let proof = inputs::load::<RollupInput>(batch)?.inclusion_proof(guest_dir, index)?;
+    let encoded = serde_json::to_string_pretty(&proof)?;
+    match out {
+        Some(out) => std::fs::write(&out, encoded)?,
+        None => println!("{}", encoded),
     }
     Ok(())
 }
 
-fn run_zkcondition() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in zkcondition_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.condition_type)?)
-            .add_input(&to_vec(&tv.value)?)
-            .build()?;
-        let guest_path = "../guest/main_zkcondition";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("ZkCondition", i, valid);
-        receipt.verify(guest_path)?;
+fn verify_inclusion(guest_dir: &Path, rollup: &Path, proof: &Path) -> Result<(), Box<dyn Error>> {
+    let bundle = ProofBundle::load(rollup)?;
+    if bundle.circuit != Circuit::Rollup {
+        return Err(format!("{} is not a rollup bundle", rollup.display()).into());
     }
+    bundle.verify(guest_dir)?;
+    let summary = RollupSummary::from_journal(&bundle.receipt.journal)?;
+    let proof: InclusionProof = inputs::load(proof)?;
+    if !proof.verify(&summary.root, summary.count) {
+        return Err("inclusion proof does not match the rollup root".into());
+    }
+    println!(
+        "[Rollup] Receipt {} of {} (image {}) is included in root 0x{}",
+        proof.index,
+        summary.count,
+        summary.image_id,
+        hex::encode(summary.root)
+    );
     Ok(())
 }
 
-fn run_zksplit() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in zksplit_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.recipients)?)
-            .add_input(&to_vec(&tv.amounts)?)
-            .build()?;
-        let guest_path = "../guest/main_zksplit";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("ZkSplit", i, valid);
-        receipt.verify(guest_path)?;
+fn aggregate_input(notes: &Path, filter: PaymentFilter, out: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let notes: Vec<TreeNote> = inputs::load(notes)?;
+    let input = AggregateInput::from_notes(&notes, filter)?;
+    let min_total = input.min_total().ok_or("matching notes overflow u64")?;
+    let encoded = serde_json::to_string_pretty(&input)?;
+    match out {
+        Some(out) => std::fs::write(&out, encoded)?,
+        None => println!("{}", encoded),
     }
+    eprintln!(
+        "[Aggregate] Root 0x{} over {} notes; {} match, min_total {}",
+        hex::encode(input.merkle_root),
+        notes.len(),
+        input.notes.len(),
+        min_total
+    );
     Ok(())
 }
 
-fn run_zkstream() -> Result<(), Box<dyn Error>> {
-    for (i, tv) in zkstream_test_vectors().into_iter().enumerate() {
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&tv.stream_id)?)
-            .add_input(&to_vec(&tv.total_amount)?)
-            .build()?;
-        let guest_path = "../guest/main_zkstream";
-        let prover = default_prover();
-        let receipt = prover.prove(env, guest_path)?;
-        let valid: bool = receipt.journal.decode()?;
-        print_result("ZkStream", i, valid);
-        receipt.verify(guest_path)?;
-    }
+fn verify_audit_log(
+    log: &Path,
+    public_key: &str,
+    known_events: Option<u64>,
+    known_head: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let known = match (known_events, known_head) {
+        (Some(events), Some(head)) => Some(ChainHead { events, head: parse_bytes32(head)? }),
+        _ => None,
+    };
+    let head = zkscanner::verify_audit_log_file(log, &parse_bytes32(public_key)?, known.as_ref())?;
+    println!("[AuditLog] {} events verified, head 0x{}", head.events, hex::encode(head.head));
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: host <merkle|audit|transfer|withdraw|nullifier|zkcondition|zksplit|zkstream>");
-        return Ok(());
-    }
-    match args[1].as_str() {
-        "merkle" => run_merkle(),
-        "audit" => run_audit(),
-        "transfer" => run_transfer(),
-        "withdraw" => run_withdraw(),
-        "nullifier" => run_nullifier(),
-        "zkcondition" => run_zkcondition(),
-        "zksplit" => run_zksplit(),
-        "zkstream" => run_zkstream(),
-        _ => {
-            println!("Unknown mode: {}", args[1]);
-            Ok(())
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Prove { circuit, input, out, receipt_kind } => {
+            prove(&cli.guest_dir, circuit, &input, out, receipt_kind)
+        }
+        Command::Verify { bundle } => verify(&cli.guest_dir, &bundle),
+        Command::Inspect { bundle } => inspect(&bundle),
+        Command::Export { bundle, format, out } => export(&bundle, format, out),
+        Command::Inclusion { batch, index, out } => inclusion(&cli.guest_dir, &batch, index, out),
+        Command::VerifyInclusion { rollup, proof } => verify_inclusion(&cli.guest_dir, &rollup, &proof),
+        Command::VerifyAuditLog { log, public_key, known_events, known_head } => {
+            verify_audit_log(&log, &public_key, known_events, known_head.as_deref())
+        }
+        Command::Scan { wallet, filter, checkpoint, config, verbose } => {
+            scan::run(&wallet, filter.as_deref(), checkpoint.as_deref(), config.as_deref(), verbose)
         }
+        Command::AuditScan { wallet, delegation, address, config, verbose } => {
+            scan::run_delegated(&wallet, &delegation, &address, config.as_deref(), verbose)
+        }
+        Command::AggregateInput { notes, recipient, start_time, end_time, out } => {
+            aggregate_input(&notes, PaymentFilter { recipient, start_time, end_time }, out)
+        }
+        Command::Vectors { circuit } => vectors::run(&cli.guest_dir, circuit),
     }
-} 
\ No newline at end of file
+}
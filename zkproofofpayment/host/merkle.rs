@@ -0,0 +1,125 @@
+use crate::test_vectors::DEPTH;
+use crate::utils::Hash;
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
+
+/// Poseidon over two BN254 field elements, as `poseidon_hash2` in the guests' `merkle.rs`.
+pub fn poseidon_hash2(a: Hash, b: Hash) -> Hash {
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).expect("Poseidon supports two inputs");
+    poseidon.hash_bytes_be(&[&a, &b]).expect("inputs are below the field modulus")
+}
+
+/// A `u64` as a big-endian field element, the way the guests pad amounts and timestamps.
+pub fn u64_field(value: u64) -> Hash {
+    let mut padded = [0u8; 32];
+    padded[24..].copy_from_slice(&value.to_be_bytes());
+    padded
+}
+
+/// The root `verify_merkle_proof` reaches from `leaf` along a path.
+pub fn path_root(leaf: Hash, path_elements: &[Hash; DEPTH], path_indices: &[u8; DEPTH]) -> Hash {
+    path_elements.iter().zip(path_indices).fold(leaf, |current, (&sibling, &index)| {
+        if index == 0 {
+            poseidon_hash2(current, sibling)
+        } else {
+            poseidon_hash2(sibling, current)
+        }
+    })
+}
+
+/// Tree leaf for a note created at `timestamp`, as `timestamped_leaf` in the guests' `note.rs`.
+pub fn timestamped_leaf(commitment: Hash, timestamp: u64) -> Hash {
+    poseidon_hash2(commitment, u64_field(timestamp))
+}
+
+/// Depth-`DEPTH` Poseidon tree over leaves in insertion order. Positions past the last leaf
+/// hold zero, so only the filled part of the tree is stored.
+pub struct NoteTree {
+    /// `levels[0]` are the leaves, `levels[DEPTH]` holds the root if there is any leaf.
+    levels: Vec<Vec<Hash>>,
+    /// Root of an empty subtree at each height.
+    zeros: [Hash; DEPTH + 1],
+}
+
+impl NoteTree {
+    pub fn new(leaves: Vec<Hash>) -> Self {
+        let mut zeros = [[0u8; 32]; DEPTH + 1];
+        for height in 1..=DEPTH {
+            zeros[height] = poseidon_hash2(zeros[height - 1], zeros[height - 1]);
+        }
+        let mut levels = vec![leaves];
+        for height in 0..DEPTH {
+            let above = levels[height]
+                .chunks(2)
+                .map(|pair| poseidon_hash2(pair[0], pair.get(1).copied().unwrap_or(zeros[height])))
+                .collect();
+            levels.push(above);
+        }
+        NoteTree { levels, zeros }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels[DEPTH].first().copied().unwrap_or(self.zeros[DEPTH])
+    }
+
+    /// Merkle path of the leaf at `index`, in the form `verify_merkle_proof` takes.
+    pub fn path(&self, index: usize) -> Option<([Hash; DEPTH], [u8; DEPTH])> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut path_elements = [[0u8; 32]; DEPTH];
+        let mut path_indices = [0u8; DEPTH];
+        let mut position = index;
+        for height in 0..DEPTH {
+            path_elements[height] = self.levels[height].get(position ^ 1).copied().unwrap_or(self.zeros[height]);
+            path_indices[height] = (position & 1) as u8;
+            position >>= 1;
+        }
+        Some((path_elements, path_indices))
+    }
+}
+
+/// The audit ID `main_audit` expects for a note opened with `view_key`.
+pub fn audit_id(note_commitment: Hash, view_key: Hash, amount: u64) -> Hash {
+    poseidon_hash2(poseidon_hash2(note_commitment, view_key), u64_field(amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hex_to_bytes32;
+
+    #[test]
+    fn test_poseidon_matches_circom_and_paths_fold_leaf_first() {
+        // Poseidon([1, 2]) from the circomlib reference implementation.
+        let expected = hex_to_bytes32("0x115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+        assert_eq!(poseidon_hash2(u64_field(1), u64_field(2)), expected);
+
+        let leaf = u64_field(7);
+        let mut path_elements = [[0u8; 32]; DEPTH];
+        path_elements[0] = u64_field(9);
+        let mut path_indices = [0u8; DEPTH];
+        let left = path_root(leaf, &path_elements, &path_indices);
+        path_indices[0] = 1;
+        let right = path_root(leaf, &path_elements, &path_indices);
+        let above = |first| (1..DEPTH).fold(first, |current, _| poseidon_hash2(current, [0u8; 32]));
+        assert_eq!(left, above(poseidon_hash2(leaf, u64_field(9))));
+        assert_eq!(right, above(poseidon_hash2(u64_field(9), leaf)));
+    }
+
+    #[test]
+    fn test_note_tree_paths_reach_its_root() {
+        let leaves: Vec<Hash> = (1..=5).map(|i| timestamped_leaf(u64_field(i), 100 + i)).collect();
+        let tree = NoteTree::new(leaves.clone());
+        for (index, leaf) in leaves.iter().enumerate() {
+            let (path_elements, path_indices) = tree.path(index).unwrap();
+            assert_eq!(path_root(*leaf, &path_elements, &path_indices), tree.root());
+        }
+        assert!(tree.path(leaves.len()).is_none());
+        assert_ne!(NoteTree::new(leaves[..4].to_vec()).root(), tree.root());
+    }
+}
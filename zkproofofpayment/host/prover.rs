@@ -0,0 +1,69 @@
+use crate::circuits::Circuit;
+use clap::ValueEnum;
+use risc0_zkvm::{
+    compute_image_id, default_prover, sha::Digest, ExecutorEnv, InnerReceipt, ProverOpts, Receipt,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Default location of the built guest binaries, relative to the host crate.
+pub const DEFAULT_GUEST_DIR: &str = "../guest";
+
+/// Which receipt the prover should produce.
+///
+/// Composite receipts carry one STARK per segment and are the cheapest to produce. Succinct
+/// receipts compress those into a single constant-size STARK. Groth16 receipts wrap the succinct
+/// receipt in a SNARK that can be checked on chain (EVM or Solana).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptKind {
+    #[default]
+    Composite,
+    Succinct,
+    Groth16,
+}
+
+impl ReceiptKind {
+    fn prover_opts(self) -> ProverOpts {
+        match self {
+            ReceiptKind::Composite => ProverOpts::composite(),
+            ReceiptKind::Succinct => ProverOpts::succinct(),
+            ReceiptKind::Groth16 => ProverOpts::groth16(),
+        }
+    }
+
+    /// The kind of an existing receipt. Fake (dev-mode) receipts are rejected.
+    pub fn of(receipt: &Receipt) -> Result<Self, Box<dyn Error>> {
+        match receipt.inner {
+            InnerReceipt::Composite(_) => Ok(ReceiptKind::Composite),
+            InnerReceipt::Succinct(_) => Ok(ReceiptKind::Succinct),
+            InnerReceipt::Groth16(_) => Ok(ReceiptKind::Groth16),
+            _ => Err("unsupported receipt kind (dev-mode receipts cannot be bundled)".into()),
+        }
+    }
+}
+
+/// A guest ELF loaded from disk together with its image ID.
+pub struct Guest {
+    pub elf: Vec<u8>,
+    pub image_id: Digest,
+}
+
+impl Guest {
+    pub fn load(guest_dir: &Path, circuit: Circuit) -> Result<Self, Box<dyn Error>> {
+        let path = guest_dir.join(circuit.guest_name());
+        let elf = fs::read(&path).map_err(|e| format!("failed to read guest {}: {}", path.display(), e))?;
+        let image_id = compute_image_id(&elf)?;
+        Ok(Guest { elf, image_id })
+    }
+
+    /// Prove one execution of this guest and check the receipt before returning it.
+    pub fn prove(&self, env: ExecutorEnv<'_>, kind: ReceiptKind) -> Result<Receipt, Box<dyn Error>> {
+        let prover = default_prover();
+        let receipt = prover.prove_with_opts(env, &self.elf, &kind.prover_opts())?.receipt;
+        receipt.verify(self.image_id)?;
+        Ok(receipt)
+    }
+}
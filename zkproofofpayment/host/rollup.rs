@@ -0,0 +1,248 @@
+use crate::bundle::ProofBundle;
+use crate::circuits::Circuit;
+use crate::prover::Guest;
+use crate::utils::{hex32, Hash};
+use risc0_zkvm::sha::{Digest, Impl, Sha256};
+use risc0_zkvm::{ExecutorEnvBuilder, Journal};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// Must match guest/rollup.rs.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_prefixed(prefix: u8, parts: &[&[u8]]) -> Hash {
+    let mut data = vec![prefix];
+    for part in parts {
+        data.extend_from_slice(part);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(Impl::hash_bytes(&data).as_bytes());
+    out
+}
+
+pub fn journal_leaf(journal: &[u8]) -> Hash {
+    hash_prefixed(LEAF_PREFIX, &[journal])
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_prefixed(NODE_PREFIX, &[left, right]),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Root of the rollup tree over `leaves`, computed exactly as the rollup guest does. Only the
+/// tests need it: the root itself always comes from the rollup journal.
+#[cfg(test)]
+pub fn rollup_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Circuit of the proof-of-payment receipts a rollup batches.
+pub const PAYMENT_CIRCUIT: Circuit = Circuit::Transfer;
+
+/// Image ID of the local [`PAYMENT_CIRCUIT`] guest. The rollup guest verifies its receipts
+/// against whatever image ID the prover gives it, so this is what both sides compare with.
+pub fn payment_image_id(guest_dir: &Path) -> Result<Digest, Box<dyn Error>> {
+    Ok(Guest::load(guest_dir, PAYMENT_CIRCUIT)?.image_id)
+}
+
+/// Bundles to roll up. All of them must be proof-of-payment bundles.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RollupInput {
+    pub bundles: Vec<PathBuf>,
+}
+
+impl RollupInput {
+    /// Load every bundle of the batch and verify it against the local payment guest in
+    /// `guest_dir`.
+    pub fn load_bundles(&self, guest_dir: &Path) -> Result<Vec<ProofBundle>, Box<dyn Error>> {
+        if self.bundles.is_empty() {
+            return Err("rollup batch is empty".into());
+        }
+        let mut bundles = Vec::with_capacity(self.bundles.len());
+        for path in &self.bundles {
+            let bundle = ProofBundle::load(path)?;
+            if bundle.circuit != PAYMENT_CIRCUIT {
+                let msg = format!("{} is a {:?} bundle, not a payment bundle", path.display(), bundle.circuit);
+                return Err(msg.into());
+            }
+            bundle.verify(guest_dir).map_err(|e| format!("{}: {}", path.display(), e))?;
+            bundles.push(bundle);
+        }
+        Ok(bundles)
+    }
+
+    /// Write the inputs in the order `main_rollup` reads them and add every receipt as an
+    /// assumption.
+    pub fn write_env(&self, guest_dir: &Path, builder: &mut ExecutorEnvBuilder<'_>) -> Result<(), Box<dyn Error>> {
+        let bundles = self.load_bundles(guest_dir)?;
+        let image_id: [u32; 8] = payment_image_id(guest_dir)?.into();
+        builder.write(&image_id)?.write(&(bundles.len() as u32))?;
+        for bundle in bundles {
+            builder.write(&bundle.receipt.journal.bytes)?;
+            builder.add_assumption(bundle.receipt);
+        }
+        Ok(())
+    }
+
+    /// Inclusion proof for the bundle at `index` against the batch root.
+    pub fn inclusion_proof(&self, guest_dir: &Path, index: usize) -> Result<InclusionProof, Box<dyn Error>> {
+        let journals = self
+            .load_bundles(guest_dir)?
+            .into_iter()
+            .map(|b| b.receipt.journal.bytes)
+            .collect::<Vec<_>>();
+        InclusionProof::new(&journals, index)
+    }
+}
+
+/// Public outputs of a rollup receipt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollupSummary {
+    pub image_id: Digest,
+    pub count: u32,
+    #[serde(with = "hex32")]
+    pub root: Hash,
+}
+
+impl RollupSummary {
+    pub fn from_journal(journal: &Journal) -> Result<Self, Box<dyn Error>> {
+        let (image_id, count, root): ([u32; 8], u32, Hash) = journal.decode()?;
+        Ok(RollupSummary { image_id: Digest::from(image_id), count, root })
+    }
+}
+
+/// One step of an inclusion path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sibling {
+    #[serde(with = "hex32")]
+    pub hash: Hash,
+    /// Whether the sibling is the left child at this level.
+    pub left: bool,
+}
+
+/// Proof that one receipt journal is part of a rollup batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub index: usize,
+    #[serde(with = "hex")]
+    pub journal: Vec<u8>,
+    pub path: Vec<Sibling>,
+}
+
+impl InclusionProof {
+    pub fn new(journals: &[Vec<u8>], index: usize) -> Result<Self, Box<dyn Error>> {
+        let journal = journals
+            .get(index)
+            .ok_or_else(|| format!("index {} out of range for a batch of {}", index, journals.len()))?
+            .clone();
+        let mut level: Vec<Hash> = journals.iter().map(|j| journal_leaf(j)).collect();
+        let mut position = index;
+        let mut path = Vec::new();
+        while level.len() > 1 {
+            let sibling = position ^ 1;
+            // A node without a sibling is carried up unchanged and adds no step.
+            if sibling < level.len() {
+                path.push(Sibling { hash: level[sibling], left: sibling < position });
+            }
+            level = next_level(&level);
+            position /= 2;
+        }
+        Ok(InclusionProof { index, journal, path })
+    }
+
+    /// Check the journal against a rollup root and batch size, e.g. `RollupSummary::root` and
+    /// `RollupSummary::count` of a verified receipt. The path must have exactly the shape of
+    /// `index` in a batch of `count`, so a valid proof also proves the index.
+    pub fn verify(&self, root: &Hash, count: u32) -> bool {
+        let mut width = count as usize;
+        if self.index >= width {
+            return false;
+        }
+        let mut steps = self.path.iter();
+        let mut acc = journal_leaf(&self.journal);
+        let mut position = self.index;
+        while width > 1 {
+            let sibling = position ^ 1;
+            if sibling < width {
+                let Some(step) = steps.next() else { return false };
+                if step.left != (sibling < position) {
+                    return false;
+                }
+                acc = if step.left {
+                    hash_prefixed(NODE_PREFIX, &[&step.hash, &acc])
+                } else {
+                    hash_prefixed(NODE_PREFIX, &[&acc, &step.hash])
+                };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+        steps.next().is_none() && acc == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journals(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| format!("journal-{}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_inclusion_proofs_verify_for_every_index() {
+        for n in 1..=9 {
+            let batch = journals(n);
+            let leaves: Vec<Hash> = batch.iter().map(|j| journal_leaf(j)).collect();
+            let root = rollup_root(&leaves);
+            for index in 0..n {
+                let proof = InclusionProof::new(&batch, index).unwrap();
+                assert!(proof.verify(&root, n as u32), "n={} index={}", n, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inclusion_proof_rejects_tampered_journal() {
+        let batch = journals(5);
+        let leaves: Vec<Hash> = batch.iter().map(|j| journal_leaf(j)).collect();
+        let root = rollup_root(&leaves);
+        let mut proof = InclusionProof::new(&batch, 3).unwrap();
+        proof.journal = b"journal-9".to_vec();
+        assert!(!proof.verify(&root, 5));
+        assert!(InclusionProof::new(&batch, 5).is_err());
+    }
+
+    #[test]
+    fn test_inclusion_proof_pins_the_index() {
+        let batch = journals(5);
+        let leaves: Vec<Hash> = batch.iter().map(|j| journal_leaf(j)).collect();
+        let root = rollup_root(&leaves);
+        let proof = InclusionProof::new(&batch, 4).unwrap();
+        assert!(proof.verify(&root, 5));
+        // The same path cannot be passed off as another position or a larger batch.
+        for index in 0..4 {
+            assert!(!InclusionProof { index, ..proof.clone() }.verify(&root, 5), "index={}", index);
+        }
+        assert!(!proof.verify(&root, 4));
+        assert!(!proof.verify(&root, 6));
+        let mut swapped = InclusionProof::new(&batch, 2).unwrap();
+        swapped.index = 3;
+        assert!(!swapped.verify(&root, 5));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::inputs;
+use crate::utils::parse_bytes32;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+use zkscanner::{
+    scan_delegated, scan_wallet_incremental, scan_wallet_notes_with_stats, AuditEvent, BoxedLogger,
+    DecryptedNote, EncryptedNote, ExternalLogger, IncomingViewKey, PaymentAddress, ScanCheckpoint,
+    ScannerConfig, SignedDelegation, Wallet,
+};
+
+/// An epoch view key, as handed to an auditor for one epoch.
+#[derive(Deserialize)]
+struct EpochKeyFile {
+    epoch: u32,
+    key: String,
+}
+
+/// On-disk wallet description: hex incoming view keys plus the encrypted notes to scan. An
+/// auditor's file may list no keys and be scanned with a delegation instead.
+#[derive(Deserialize)]
+struct WalletFile {
+    #[serde(default)]
+    view_keys: Vec<String>,
+    #[serde(default)]
+    epoch_keys: Vec<EpochKeyFile>,
+    notes: Vec<EncryptedNote>,
+}
+
+impl WalletFile {
+    fn into_wallet(self) -> Result<Wallet, Box<dyn Error>> {
+        let view_keys = self
+            .view_keys
+            .iter()
+            .map(|k| Ok(IncomingViewKey::from_bytes(parse_bytes32(k)?)?))
+            .chain(
+                self.epoch_keys
+                    .iter()
+                    .map(|k| Ok(IncomingViewKey::from_epoch_bytes(k.epoch, parse_bytes32(&k.key)?)?)),
+            )
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        Ok(Wallet { view_keys, notes: self.notes })
+    }
+}
+
+/// Prints audit events to stderr so they do not mix with scan results.
+struct StderrLogger;
+
+impl ExternalLogger for StderrLogger {
+    fn log_event(&self, event: &AuditEvent) {
+        eprintln!("[audit][{}] {} {}", event.timestamp, event.action, event.details);
+    }
+}
+
+/// Config used when neither `--config` nor `ZKAUDIT_CONFIG` names one: the repository's own,
+/// built into the binary. Relative paths in it are relative to the working directory.
+const DEFAULT_CONFIG: &str = include_str!("../../config/default.toml");
+
+/// A config file given with `--config` or `ZKAUDIT_CONFIG` must exist. Relative paths in it are
+/// relative to the file's directory.
+fn load_config(config_path: Option<&Path>) -> Result<ScannerConfig, Box<dyn Error>> {
+    Ok(match config_path {
+        Some(path) => {
+            ScannerConfig::load(path).map_err(|e| format!("failed to read config {}: {}", path.display(), e))?
+        }
+        None => ScannerConfig::parse(DEFAULT_CONFIG, Path::new(""))
+            .map_err(|e| format!("failed to read the built-in config: {}", e))?,
+    })
+}
+
+/// `--verbose` wins over the configured backend.
+fn audit_logger(config: &ScannerConfig, verbose: bool) -> Result<Option<BoxedLogger>, Box<dyn Error>> {
+    if verbose {
+        return Ok(Some(Box::new(StderrLogger)));
+    }
+    Ok(config.build_logger()?)
+}
+
+pub fn run(
+    wallet_path: &Path,
+    filter: Option<&str>,
+    checkpoint_path: Option<&Path>,
+    config_path: Option<&Path>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let wallet = inputs::load::<WalletFile>(wallet_path)?.into_wallet()?;
+    let now = chrono::Utc::now().timestamp();
+    let config = load_config(config_path)?;
+    let logger = audit_logger(&config, verbose)?;
+    // A missing checkpoint file starts a full scan and is created afterwards.
+    let mut checkpoint = match checkpoint_path {
+        Some(path) if path.exists() => Some((path, inputs::load::<ScanCheckpoint>(path)?)),
+        Some(path) => Some((path, ScanCheckpoint::default())),
+        None => None,
+    };
+    let (found, stats) = match &mut checkpoint {
+        None => scan_wallet_notes_with_stats(&wallet, filter, now, logger.as_ref(), &config.redaction),
+        Some((_, checkpoint)) => scan_wallet_incremental(&wallet, checkpoint, now, logger.as_ref(), &config.redaction)?,
+    };
+    print_notes(&found, wallet.notes.len());
+    for (key, stats) in &stats.keys {
+        println!(
+            "[Scan] key {} decrypted={} expired={} duplicates={} rejected={}",
+            key, stats.decrypted, stats.expired, stats.duplicates, stats.rejected
+        );
+    }
+    // The checkpoint only moves past these notes once all their audit events are written, so
+    // notes whose events were lost are scanned again next time.
+    close_logger(logger)?;
+    if let Some((path, checkpoint)) = checkpoint {
+        std::fs::write(path, serde_json::to_string_pretty(&checkpoint)?)?;
+    }
+    Ok(())
+}
+
+/// Scan with a delegated auditor key instead of the wallet's own keys. Notes outside the
+/// delegation's scope are reported to the audit log, not printed.
+pub fn run_delegated(
+    wallet_path: &Path,
+    delegation_path: &Path,
+    address_path: &Path,
+    config_path: Option<&Path>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let wallet = inputs::load::<WalletFile>(wallet_path)?.into_wallet()?;
+    let delegation = inputs::load::<SignedDelegation>(delegation_path)?;
+    let address = inputs::load::<PaymentAddress>(address_path)?;
+    let now = chrono::Utc::now().timestamp();
+    let config = load_config(config_path)?;
+    let logger = audit_logger(&config, verbose)?;
+    let found = scan_delegated(&delegation, &address, &wallet.notes, now, logger.as_ref(), &config.redaction)?;
+    print_notes(&found, wallet.notes.len());
+    close_logger(logger)
+}
+
+/// Write out queued audit events, failing the command if any were lost.
+fn close_logger(logger: Option<BoxedLogger>) -> Result<(), Box<dyn Error>> {
+    if let Some(logger) = logger {
+        logger.close().map_err(|e| format!("audit log incomplete: {}", e))?;
+    }
+    Ok(())
+}
+
+fn print_notes(found: &[DecryptedNote], total: usize) {
+    println!("[Scan] {} of {} notes decrypted", found.len(), total);
+    for note in found {
+        println!(
+            "[Scan] {} key={} amount={} asset={} recipient={} reference={} expired={}",
+            note.metadata,
+            note.view_key,
+            note.amount(),
+            note.asset_id(),
+            note.recipient(),
+            note.reference().unwrap_or("-"),
+            note.expired
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use crate::disclosure::{DisclosureInput, NoteField};
+use crate::merkle::{audit_id, path_root};
 use crate::utils::{Hash, hex_to_bytes32};
+use zkscanner::NotePayload;
 pub const DEPTH: usize = 32;
 
 pub struct MerkleTestVector {
@@ -6,6 +9,8 @@ pub struct MerkleTestVector {
     pub leaf: Hash,
     pub path_elements: [Hash; DEPTH],
     pub path_indices: [u8; DEPTH],
+    /// Whether the path reaches `root`; `main_merkle` refuses to prove it otherwise.
+    pub valid: bool,
 }
 
 pub struct AuditTestVector {
@@ -16,6 +21,8 @@ pub struct AuditTestVector {
     pub purpose: u64,
     pub audit_id: Hash,
     pub merkle_root: Hash,
+    /// The `is_valid` the guest should commit.
+    pub valid: bool,
 }
 
 pub struct TransferTestVector {
@@ -50,41 +57,60 @@ pub struct ZkStreamTestVector {
 }
 
 pub fn merkle_test_vectors() -> Vec<MerkleTestVector> {
+    let leaf = hex_to_bytes32("0xfedcba0987654321");
+    let path_elements = std::array::from_fn(|i| hex_to_bytes32(&format!("0x{:02x}", i + 1)));
+    let path_indices = std::array::from_fn(|i| (i % 3 == 0) as u8);
+    let root = path_root(leaf, &path_elements, &path_indices);
     vec![
+        // Valid: the root is recomputed on the host from the same path
+        MerkleTestVector { root, leaf, path_elements, path_indices, valid: true },
+        // Invalid: a different leaf does not reach that root
         MerkleTestVector {
-            root: hex_to_bytes32("0x1234567890abcdef"),
-            leaf: hex_to_bytes32("0xfedcba0987654321"),
-            path_elements: [[0u8; 32]; DEPTH],
-            path_indices: [0u8; DEPTH],
-        },
-        MerkleTestVector {
-            root: hex_to_bytes32("0xabcdefabcdefabcd"),
+            root,
             leaf: hex_to_bytes32("0x1111111111111111"),
-            path_elements: [[1u8; 32]; DEPTH],
-            path_indices: [1u8; DEPTH],
+            path_elements,
+            path_indices,
+            valid: false,
         },
     ]
 }
 
 pub fn audit_test_vectors() -> Vec<AuditTestVector> {
+    let note_commitment = hex_to_bytes32("0x11111111111111111111111111111111");
+    let view_key = hex_to_bytes32("0x22222222222222222222222222222222");
     vec![
+        // Valid: the audit ID is computed on the host the way the guest does
         AuditTestVector {
-            note_commitment: hex_to_bytes32("0x11111111111111111111111111111111"),
-            view_key: hex_to_bytes32("0x22222222222222222222222222222222"),
+            note_commitment,
+            view_key,
             amount: 100,
             timestamp: 1234560000,
             purpose: 1,
-            audit_id: [0u8; 32], // Placeholder, should match guest computation
+            audit_id: audit_id(note_commitment, view_key, 100),
             merkle_root: hex_to_bytes32("0x33333333333333333333333333333333"),
+            valid: true,
         },
+        // Invalid: the audit ID was issued for a different amount
         AuditTestVector {
-            note_commitment: hex_to_bytes32("0xabcdefabcdefabcdefabcdefabcdefab"),
-            view_key: hex_to_bytes32("0x44444444444444444444444444444444"),
+            note_commitment,
+            view_key,
             amount: 200,
             timestamp: 1234550000,
             purpose: 2,
-            audit_id: [0u8; 32], // Placeholder, should match guest computation
+            audit_id: audit_id(note_commitment, view_key, 100),
             merkle_root: hex_to_bytes32("0x55555555555555555555555555555555"),
+            valid: false,
+        },
+        // Invalid: the timestamp is after the guest's current time
+        AuditTestVector {
+            note_commitment,
+            view_key,
+            amount: 100,
+            timestamp: 1234567891,
+            purpose: 1,
+            audit_id: audit_id(note_commitment, view_key, 100),
+            merkle_root: hex_to_bytes32("0x33333333333333333333333333333333"),
+            valid: false,
         },
     ]
 }
@@ -208,4 +234,25 @@ pub fn zkstream_test_vectors() -> Vec<ZkStreamTestVector> {
             total_amount: 1000,
         },
     ]
-} 
\ No newline at end of file
+} 
+
+pub fn disclosure_test_vectors() -> Vec<DisclosureInput> {
+    let note = NotePayload {
+        amount: 1500,
+        recipient: "bob".to_string(),
+        memo: Some("rent for march".to_string()),
+        ..NotePayload::default()
+    };
+    vec![
+        // Reveal nothing but the commitment
+        DisclosureInput { note: note.clone(), blinding: hex_to_bytes32("0x4242"), reveal: vec![] },
+        // Reveal the amount only
+        DisclosureInput { note: note.clone(), blinding: hex_to_bytes32("0x4242"), reveal: vec![NoteField::Amount] },
+        // Reveal every field of a note without a memo
+        DisclosureInput {
+            note: NotePayload { memo: None, ..note },
+            blinding: hex_to_bytes32("0x4343"),
+            reveal: vec![NoteField::Amount, NoteField::Recipient, NoteField::Memo],
+        },
+    ]
+}
@@ -11,4 +11,94 @@ pub fn hex_to_bytes32(hex: &str) -> Hash {
 
 pub fn print_result(label: &str, i: usize, valid: bool) {
     println!("[{}][Test {}] Proof valid: {}", label, i, valid);
-} 
\ No newline at end of file
+}
+
+/// Parse a 32-byte value read from a user-supplied file. Unlike `hex_to_bytes32` it must be
+/// exactly 64 hex digits, so a truncated key or hash is an error rather than silently padded.
+pub fn parse_bytes32(hex: &str) -> Result<Hash, String> {
+    let hex = hex.trim_start_matches("0x");
+    let hex_bytes = hex::decode(hex).map_err(|e| format!("invalid hex '{}': {}", hex, e))?;
+    hex_bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("hex value '{}' is {} bytes, expected 32", hex, bytes.len()))
+}
+
+/// Serde helpers for 32-byte hashes written as (optionally `0x`-prefixed) hex strings.
+pub mod hex32 {
+    use super::{parse_bytes32, Hash};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Hash, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hash, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_bytes32(&s).map_err(D::Error::custom)
+    }
+
+    /// Optional hashes; absent or `null` deserializes to `None`.
+    pub mod option {
+        use super::super::{parse_bytes32, Hash};
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &Option<Hash>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(hash) => serializer.serialize_some(&format!("0x{}", hex::encode(hash))),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Hash>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|s| parse_bytes32(&s).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+
+    /// Fixed-length arrays of hashes, e.g. Merkle path elements.
+    pub mod array {
+        use super::super::{parse_bytes32, Hash};
+        use serde::{de::Error, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer, const N: usize>(
+            value: &[Hash; N],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(N))?;
+            for hash in value {
+                seq.serialize_element(&format!("0x{}", hex::encode(hash)))?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+            deserializer: D,
+        ) -> Result<[Hash; N], D::Error> {
+            let items = Vec::<String>::deserialize(deserializer)?;
+            if items.len() != N {
+                return Err(D::Error::invalid_length(items.len(), &"a fixed-length hash array"));
+            }
+            let mut out = [[0u8; 32]; N];
+            for (slot, item) in out.iter_mut().zip(items.iter()) {
+                *slot = parse_bytes32(item).map_err(D::Error::custom)?;
+            }
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bytes32_requires_exactly_32_bytes() {
+        let full = format!("0x{}", "ab".repeat(32));
+        assert_eq!(parse_bytes32(&full), Ok([0xab; 32]));
+        assert_eq!(parse_bytes32(&full[2..]), Ok([0xab; 32]));
+        assert!(parse_bytes32("0xabcd").is_err());
+        assert!(parse_bytes32(&format!("{}00", full)).is_err());
+        assert!(parse_bytes32("0xzz").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::circuits::Circuit;
+use crate::disclosure::DisclosedFields;
+use crate::prover::{Guest, ReceiptKind};
+use crate::test_vectors::*;
+use crate::utils::{print_result, Hash};
+use risc0_zkvm::{ExecutorEnv, Receipt};
+use std::error::Error;
+use std::path::Path;
+
+/// Prove and verify every built-in test vector for `circuit`.
+pub fn run(guest_dir: &Path, circuit: Circuit) -> Result<(), Box<dyn Error>> {
+    let guest = Guest::load(guest_dir, circuit)?;
+    match circuit {
+        Circuit::Merkle => run_merkle(&guest),
+        Circuit::Audit => run_audit(&guest),
+        Circuit::Transfer => run_transfer(&guest),
+        Circuit::Withdraw => run_withdraw(&guest),
+        Circuit::Nullifier => run_nullifier(&guest),
+        Circuit::Zkcondition => run_zkcondition(&guest),
+        Circuit::Zksplit => run_zksplit(&guest),
+        Circuit::Zkstream => run_zkstream(&guest),
+        Circuit::Disclosure => run_disclosure(&guest),
+        Circuit::Aggregate | Circuit::Rollup => {
+            Err(format!("no built-in test vectors for {}", circuit.guest_name()).into())
+        }
+    }
+}
+
+fn run_merkle(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for (i, tv) in merkle_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.root)?
+            .write(&tv.leaf)?
+            .write(&tv.path_elements)?
+            .write(&tv.path_indices)?
+            .build()?;
+        // main_merkle asserts the path, so a path that misses the root yields no receipt.
+        let as_expected = match guest.prove(env, ReceiptKind::Composite) {
+            Ok(receipt) => {
+                let computed_root: Hash = receipt.journal.decode()?;
+                println!("[Merkle][Test {}] Computed root in zkVM: 0x{}", i, hex::encode(computed_root));
+                tv.valid && computed_root == tv.root
+            }
+            Err(e) => {
+                println!("[Merkle][Test {}] Proving failed: {}", i, e);
+                !tv.valid
+            }
+        };
+        check(Circuit::Merkle, i, as_expected, &mut failed);
+    }
+    outcome(Circuit::Merkle, failed)
+}
+
+fn run_audit(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for (i, tv) in audit_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.note_commitment)?
+            .write(&tv.view_key)?
+            .write(&tv.amount)?
+            .write(&tv.timestamp)?
+            .write(&tv.purpose)?
+            .write(&tv.audit_id)?
+            .write(&tv.merkle_root)?
+            .write(&None::<[u32; 8]>)?
+            .write(&None::<Hash>)?
+            .build()?;
+        let receipt = guest.prove(env, ReceiptKind::Composite)?;
+        let (is_valid, returned_merkle_root): (bool, Hash) = receipt.journal.decode()?;
+        println!("[Audit][Test {}] Proof valid: {}", i, is_valid);
+        println!("[Audit][Test {}] Merkle root: 0x{}", i, hex::encode(returned_merkle_root));
+        check(Circuit::Audit, i, is_valid == tv.valid && returned_merkle_root == tv.merkle_root, &mut failed);
+    }
+    outcome(Circuit::Audit, failed)
+}
+
+fn run_transfer(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in transfer_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.amount)?
+            .write(&tv.sender)?
+            .write(&tv.receiver)?
+            .build()?;
+        report(Circuit::Transfer, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_withdraw(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in withdraw_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.amount)?
+            .write(&tv.recipient)?
+            .build()?;
+        report(Circuit::Withdraw, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_nullifier(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in nullifier_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.nullifier)?
+            .write(&tv.secret)?
+            .build()?;
+        report(Circuit::Nullifier, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_zkcondition(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in zkcondition_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.condition_type)?
+            .write(&tv.value)?
+            .build()?;
+        report(Circuit::Zkcondition, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_zksplit(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in zksplit_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.recipients)?
+            .write(&tv.amounts)?
+            .build()?;
+        report(Circuit::Zksplit, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_zkstream(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    for (i, tv) in zkstream_test_vectors().into_iter().enumerate() {
+        let env = ExecutorEnv::builder()
+            .write(&tv.stream_id)?
+            .write(&tv.total_amount)?
+            .build()?;
+        report(Circuit::Zkstream, i, &guest.prove(env, ReceiptKind::Composite)?)?;
+    }
+    Ok(())
+}
+
+fn run_disclosure(guest: &Guest) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for (i, tv) in disclosure_test_vectors().into_iter().enumerate() {
+        let mut builder = ExecutorEnv::builder();
+        tv.write_env(&mut builder)?;
+        let receipt = guest.prove(builder.build()?, ReceiptKind::Composite)?;
+        let fields = DisclosedFields::from_journal(&receipt.journal)?;
+        // The guest must open the same commitment as the scanner and reveal only what was asked.
+        check(Circuit::Disclosure, i, fields == tv.expected_fields()?, &mut failed);
+    }
+    outcome(Circuit::Disclosure, failed)
+}
+
+fn report(circuit: Circuit, i: usize, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+    let outputs = circuit.decode_journal(&receipt.journal)?;
+    let valid = outputs["is_valid"].as_bool().unwrap_or(false);
+    print_result(circuit.label(), i, valid);
+    Ok(())
+}
+
+/// Print whether vector `i` gave the expected result, counting it in `failed` if not.
+fn check(circuit: Circuit, i: usize, as_expected: bool, failed: &mut usize) {
+    println!("[{}][Test {}] As expected: {}", circuit.label(), i, as_expected);
+    *failed += !as_expected as usize;
+}
+
+fn outcome(circuit: Circuit, failed: usize) -> Result<(), Box<dyn Error>> {
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{} {} test vector(s) did not give the expected result", n, circuit.label()).into()),
+    }
+}
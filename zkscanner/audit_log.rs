@@ -0,0 +1,466 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use crate::decrypt_notes::{AuditEvent, ExternalLogger};
+use crate::error::AuditLogError;
+use crate::nullifier::Hash;
+
+const EVENT_DOMAIN: &[u8] = b"CipherPay_audit_event";
+const CHECKPOINT_DOMAIN: &[u8] = b"CipherPay_audit_checkpoint";
+
+/// An audit event linked to the one before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainedEvent {
+    /// Position in the log, starting at 0.
+    pub seq: u64,
+    /// Hash of the previous event, all zeros for the first one.
+    #[serde(with = "hex")]
+    pub prev_hash: Hash,
+    pub event: AuditEvent,
+    #[serde(with = "hex")]
+    pub hash: Hash,
+}
+
+impl ChainedEvent {
+    fn compute_hash(seq: u64, prev_hash: &Hash, event: &AuditEvent) -> Hash {
+        let mut hasher = Sha256::new()
+            .chain_update(EVENT_DOMAIN)
+            .chain_update(seq.to_le_bytes())
+            .chain_update(prev_hash)
+            .chain_update(event.timestamp.to_le_bytes())
+            .chain_update((event.action.len() as u64).to_le_bytes())
+            .chain_update(event.action.as_bytes())
+            .chain_update((event.details.0.len() as u64).to_le_bytes());
+        for (key, value) in event.details.iter() {
+            for part in [key, value] {
+                hasher.update((part.len() as u64).to_le_bytes());
+                hasher.update(part.as_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// A signature over the chain head after the first `seq` events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCheckpoint {
+    pub seq: u64,
+    #[serde(with = "hex")]
+    pub head: Hash,
+    #[serde(with = "hex")]
+    pub signature: Vec<u8>,
+}
+
+fn checkpoint_message(seq: u64, head: &Hash) -> Vec<u8> {
+    [CHECKPOINT_DOMAIN, &seq.to_le_bytes()[..], &head[..]].concat()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuditRecord {
+    Event(ChainedEvent),
+    Checkpoint(SignedCheckpoint),
+}
+
+/// Events and checkpoints in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditLog {
+    pub records: Vec<AuditRecord>,
+}
+
+impl AuditLog {
+    /// Read a log written by [`HashChainLogger`], one JSON record per line.
+    pub fn read(path: &Path) -> Result<Self, AuditLogError> {
+        let text = fs::read_to_string(path).map_err(|e| AuditLogError::Io(e.to_string()))?;
+        let records = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| AuditLogError::Malformed(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        Ok(AuditLog { records })
+    }
+}
+
+/// State of a verified log: how many events it holds and the hash of the last one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHead {
+    pub events: u64,
+    pub head: Hash,
+}
+
+/// Check that a log is one unbroken chain, that every checkpoint is signed by `key` and that the
+/// log ends with one.
+///
+/// Inserting, deleting, reordering or editing any event breaks either a sequence number, a
+/// link or an event hash. Rewriting the whole chain after the change would still not match the
+/// signed checkpoints, and events dropped from the end take their checkpoint with them. Cutting
+/// the log back to an earlier checkpoint is only caught against a head verified before: pass
+/// the last verified head as `known` and the log must still pass through it.
+pub fn verify_audit_log(log: &AuditLog, key: &VerifyingKey, known: Option<&ChainHead>) -> Result<ChainHead, AuditLogError> {
+    let head = replay(log, key, known)?;
+    if !matches!(log.records.last(), Some(AuditRecord::Checkpoint(_))) {
+        return Err(AuditLogError::MissingCheckpoint { events: head.events });
+    }
+    Ok(head)
+}
+
+/// Walk the chain and its checkpoints, allowing events after the last checkpoint.
+fn replay(log: &AuditLog, key: &VerifyingKey, known: Option<&ChainHead>) -> Result<ChainHead, AuditLogError> {
+    let reaches_known = |state: &ChainHead| match known {
+        Some(known) if known.events == state.events && known.head != state.head => {
+            Err(AuditLogError::ForkedHead { events: known.events })
+        }
+        _ => Ok(()),
+    };
+    let mut state = ChainHead { events: 0, head: [0u8; 32] };
+    reaches_known(&state)?;
+    for record in &log.records {
+        match record {
+            AuditRecord::Event(e) => {
+                if e.seq != state.events {
+                    return Err(AuditLogError::SequenceGap { expected: state.events, found: e.seq });
+                }
+                if e.prev_hash != state.head {
+                    return Err(AuditLogError::BrokenLink { seq: e.seq });
+                }
+                if ChainedEvent::compute_hash(e.seq, &e.prev_hash, &e.event) != e.hash {
+                    return Err(AuditLogError::EditedEvent { seq: e.seq });
+                }
+                state = ChainHead { events: e.seq + 1, head: e.hash };
+                reaches_known(&state)?;
+            }
+            AuditRecord::Checkpoint(c) => {
+                if c.seq != state.events || c.head != state.head {
+                    return Err(AuditLogError::CheckpointMismatch { seq: c.seq });
+                }
+                let signature = Signature::from_slice(&c.signature)
+                    .map_err(|_| AuditLogError::BadSignature { seq: c.seq })?;
+                key.verify(&checkpoint_message(c.seq, &c.head), &signature)
+                    .map_err(|_| AuditLogError::BadSignature { seq: c.seq })?;
+            }
+        }
+    }
+    if let Some(known) = known.filter(|known| known.events > state.events) {
+        return Err(AuditLogError::Truncated { expected: known.events, found: state.events });
+    }
+    Ok(state)
+}
+
+/// [`verify_audit_log`] for a log file and a raw Ed25519 public key.
+pub fn verify_audit_log_file(path: &Path, public_key: &[u8; 32], known: Option<&ChainHead>) -> Result<ChainHead, AuditLogError> {
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| AuditLogError::InvalidKey)?;
+    verify_audit_log(&AuditLog::read(path)?, &key, known)
+}
+
+/// Where records go: kept in memory, or appended to a file with only the head kept.
+enum ChainSink {
+    Memory(AuditLog),
+    File(File),
+}
+
+struct ChainState {
+    head: ChainHead,
+    since_checkpoint: u64,
+    /// Events [`log_event`](ExternalLogger::log_event) failed to write, reported by `close`.
+    lost: u64,
+    sink: ChainSink,
+}
+
+/// Logger that appends every event to a hash chain and signs a checkpoint every
+/// `checkpoint_every` events, and over any remaining events when dropped. With a file, records
+/// are appended as JSON lines as they happen and only the chain head is kept in memory.
+pub struct HashChainLogger {
+    state: Mutex<ChainState>,
+    signing_key: SigningKey,
+    checkpoint_every: u64,
+}
+
+impl HashChainLogger {
+    pub fn new(signing_key: SigningKey, checkpoint_every: u64) -> Self {
+        HashChainLogger {
+            state: Mutex::new(ChainState {
+                head: ChainHead { events: 0, head: [0u8; 32] },
+                since_checkpoint: 0,
+                lost: 0,
+                sink: ChainSink::Memory(AuditLog::default()),
+            }),
+            signing_key,
+            checkpoint_every: checkpoint_every.max(1),
+        }
+    }
+
+    /// Continue the log at `path`, which is verified first, or start a new one. Events left
+    /// without a checkpoint, e.g. by a crash, are signed straight away.
+    pub fn open(path: &Path, signing_key: SigningKey, checkpoint_every: u64) -> Result<Self, AuditLogError> {
+        let log = if path.exists() { AuditLog::read(path)? } else { AuditLog::default() };
+        let head = replay(&log, &signing_key.verifying_key(), None)?;
+        let since_checkpoint = log
+            .records
+            .iter()
+            .rev()
+            .take_while(|r| matches!(r, AuditRecord::Event(_)))
+            .count() as u64;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AuditLogError::Io(e.to_string()))?;
+        let logger = HashChainLogger::new(signing_key, checkpoint_every);
+        let mut state = logger.state.lock().unwrap();
+        *state = ChainState { head, since_checkpoint, lost: 0, sink: ChainSink::File(file) };
+        if since_checkpoint > 0 {
+            logger.push_checkpoint(&mut state).map_err(|e| AuditLogError::Io(e.to_string()))?;
+        }
+        drop(state);
+        Ok(logger)
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    /// Current chain head, e.g. to commit into an audit proof.
+    pub fn head(&self) -> ChainHead {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).head
+    }
+
+    /// Records written so far, for a logger without a file. A file-backed logger keeps only the
+    /// head; read its file with [`AuditLog::read`] instead.
+    pub fn log(&self) -> Option<AuditLog> {
+        match &self.state.lock().unwrap_or_else(|e| e.into_inner()).sink {
+            ChainSink::Memory(log) => Some(log.clone()),
+            ChainSink::File(_) => None,
+        }
+    }
+
+    /// Sign a checkpoint over the current head now, e.g. before shutting down.
+    pub fn checkpoint(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.push_checkpoint(&mut state)
+    }
+
+    fn push_checkpoint(&self, state: &mut ChainState) -> io::Result<()> {
+        let ChainHead { events, head } = state.head;
+        let signature = self.signing_key.sign(&checkpoint_message(events, &head));
+        let record = AuditRecord::Checkpoint(SignedCheckpoint { seq: events, head, signature: signature.to_bytes().to_vec() });
+        Self::append(state, record)?;
+        state.since_checkpoint = 0;
+        Ok(())
+    }
+
+    /// Chain one event onto the head. The head only moves once the event is written, so a failed
+    /// write leaves no gap and the event can be logged again.
+    fn push_event(&self, state: &mut ChainState, event: &AuditEvent) -> io::Result<()> {
+        let ChainHead { events: seq, head: prev_hash } = state.head;
+        let hash = ChainedEvent::compute_hash(seq, &prev_hash, event);
+        Self::append(state, AuditRecord::Event(ChainedEvent { seq, prev_hash, event: event.clone(), hash }))?;
+        state.head = ChainHead { events: seq + 1, head: hash };
+        state.since_checkpoint += 1;
+        if state.since_checkpoint >= self.checkpoint_every {
+            self.push_checkpoint(state)?;
+        }
+        Ok(())
+    }
+
+    /// Write one record. A write that fails part way is cut back off the file, so the file
+    /// always ends on a whole line.
+    fn append(state: &mut ChainState, record: AuditRecord) -> io::Result<()> {
+        match &mut state.sink {
+            ChainSink::Memory(log) => log.records.push(record),
+            ChainSink::File(file) => {
+                let mut line = serde_json::to_vec(&record).expect("audit records serialize to JSON");
+                line.push(b'\n');
+                let len = file.metadata()?.len();
+                if let Err(e) = file.write_all(&line) {
+                    let _ = file.set_len(len);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for HashChainLogger {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.since_checkpoint > 0 {
+            if let Err(e) = self.push_checkpoint(&mut state) {
+                eprintln!("[audit] failed to checkpoint hash-chained log: {}", e);
+            }
+        }
+    }
+}
+
+impl ExternalLogger for HashChainLogger {
+    fn log_event(&self, event: &AuditEvent) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = self.push_event(&mut state, event) {
+            eprintln!("[audit] failed to append to hash-chained log: {}", e);
+            state.lost += 1;
+        }
+    }
+
+    /// Append the event, or return the write error with the head left where it was, so the
+    /// event can be logged again.
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.push_event(&mut state, event)
+    }
+
+    /// Sign a final checkpoint over any events since the last one, then fail if it could not be
+    /// written or if [`log_event`](ExternalLogger::log_event) lost any event.
+    fn close(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.since_checkpoint > 0 {
+            self.push_checkpoint(&mut state)?;
+        }
+        match state.lost {
+            0 => Ok(()),
+            lost => Err(io::Error::other(format!("{} audit events failed to write to the hash-chained log", lost))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::EventDetails;
+
+    fn logged(n: u64, checkpoint_every: u64) -> (HashChainLogger, AuditLog) {
+        let logger = HashChainLogger::new(SigningKey::from_bytes(&[7u8; 32]), checkpoint_every);
+        for i in 0..n {
+            logger.log_event(&AuditEvent { timestamp: i as i64, action: "decryption_attempt".into(), details: EventDetails::new().with("metadata", format!("note{}", i)) });
+        }
+        logger.checkpoint().unwrap();
+        let log = logger.log().unwrap();
+        (logger, log)
+    }
+
+    fn event_positions(log: &AuditLog) -> Vec<usize> {
+        log.records.iter().enumerate().filter(|(_, r)| matches!(r, AuditRecord::Event(_))).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_intact_log_verifies_to_its_head() {
+        let (logger, log) = logged(7, 3);
+        let head = verify_audit_log(&log, &logger.verifying_key(), None).unwrap();
+        assert_eq!(head, logger.head());
+        assert_eq!(head.events, 7);
+        assert_eq!(log.records.iter().filter(|r| matches!(r, AuditRecord::Checkpoint(_))).count(), 3);
+        let other = SigningKey::from_bytes(&[8u8; 32]).verifying_key();
+        assert_eq!(verify_audit_log(&log, &other, None), Err(AuditLogError::BadSignature { seq: 3 }));
+        // With no checkpoint at all, no key is ever checked.
+        assert_eq!(verify_audit_log(&AuditLog::default(), &other, None), Err(AuditLogError::MissingCheckpoint { events: 0 }));
+    }
+
+    #[test]
+    fn test_truncation_is_detected() {
+        let (logger, log) = logged(7, 3);
+        let key = logger.verifying_key();
+        let head = logger.head();
+
+        let mut unsigned = log.clone();
+        unsigned.records.pop();
+        assert_eq!(verify_audit_log(&unsigned, &key, None), Err(AuditLogError::MissingCheckpoint { events: 7 }));
+
+        // Cut back to the checkpoint after 6 events: valid on its own, but not against the head
+        // verified before.
+        let mut cut = log.clone();
+        cut.records.truncate(cut.records.len() - 2);
+        assert_eq!(verify_audit_log(&cut, &key, None).unwrap().events, 6);
+        assert_eq!(verify_audit_log(&cut, &key, Some(&head)), Err(AuditLogError::Truncated { expected: 7, found: 6 }));
+
+        let earlier = verify_audit_log(&cut, &key, None).unwrap();
+        assert_eq!(verify_audit_log(&log, &key, Some(&earlier)).unwrap(), head);
+        let forked = ChainHead { events: 6, head: [1u8; 32] };
+        assert_eq!(verify_audit_log(&log, &key, Some(&forked)), Err(AuditLogError::ForkedHead { events: 6 }));
+    }
+
+    #[test]
+    fn test_edits_insertions_and_deletions_are_detected() {
+        let (logger, log) = logged(7, 3);
+        let key = logger.verifying_key();
+        let events = event_positions(&log);
+
+        let mut edited = log.clone();
+        if let AuditRecord::Event(e) = &mut edited.records[events[1]] {
+            e.event.details = EventDetails::new().with("metadata", "note99");
+        }
+        assert_eq!(verify_audit_log(&edited, &key, None), Err(AuditLogError::EditedEvent { seq: 1 }));
+
+        let mut deleted = log.clone();
+        deleted.records.remove(events[4]);
+        assert_eq!(verify_audit_log(&deleted, &key, None), Err(AuditLogError::SequenceGap { expected: 4, found: 5 }));
+
+        let mut inserted = log.clone();
+        let copy = inserted.records[events[2]].clone();
+        inserted.records.insert(events[2], copy);
+        assert_eq!(verify_audit_log(&inserted, &key, None), Err(AuditLogError::SequenceGap { expected: 3, found: 2 }));
+
+        // Rewriting the chain after an edit keeps the links intact but not the signed checkpoint.
+        let rewritten = HashChainLogger::new(SigningKey::from_bytes(&[9u8; 32]), 100);
+        for record in &log.records {
+            if let AuditRecord::Event(e) = record {
+                let mut event = e.event.clone();
+                if e.seq == 0 {
+                    event.details = EventDetails::new().with("metadata", "forged");
+                }
+                rewritten.log_event(&event);
+            }
+        }
+        let mut forged = rewritten.log().unwrap();
+        forged.records.insert(3, log.records[3].clone());
+        assert_eq!(verify_audit_log(&forged, &key, None), Err(AuditLogError::CheckpointMismatch { seq: 3 }));
+    }
+
+    #[test]
+    fn test_file_backed_log_resumes_the_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.chain.jsonl");
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let event = AuditEvent { timestamp: 1, action: "note_expired".into(), details: EventDetails::new().with("metadata", "note1") };
+        HashChainLogger::open(&path, key.clone(), 2).unwrap().log_event(&event);
+        let logger = HashChainLogger::open(&path, key.clone(), 2).unwrap();
+        logger.log_event(&event);
+        assert!(logger.log().is_none());
+        let expected = logger.head();
+        // Dropping the logger signs the event logged since the last checkpoint.
+        drop(logger);
+        let head = verify_audit_log(&AuditLog::read(&path).unwrap(), &key.verifying_key(), None).unwrap();
+        assert_eq!(head.events, 2);
+        assert_eq!(head, expected);
+    }
+
+    #[test]
+    fn test_failed_writes_do_not_advance_the_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.chain.jsonl");
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let event = AuditEvent { timestamp: 1, action: "note_expired".into(), details: EventDetails::new().with("metadata", "note1") };
+        let logger = HashChainLogger::open(&path, key.clone(), 10).unwrap();
+        logger.log_event(&event);
+        let before = logger.head();
+
+        // A read-only handle makes every write fail.
+        let writable = std::mem::replace(&mut logger.state.lock().unwrap().sink, ChainSink::File(File::open(&path).unwrap()));
+        logger.log_event(&event);
+        assert!(logger.checkpoint().is_err());
+        assert_eq!(logger.head(), before);
+
+        assert!(logger.try_log_event(&event).is_err());
+        assert_eq!(logger.head(), before);
+        assert!(logger.close().is_err());
+
+        logger.state.lock().unwrap().sink = writable;
+        logger.try_log_event(&event).unwrap();
+        assert_eq!(logger.close().unwrap_err().to_string(), "1 audit events failed to write to the hash-chained log");
+        drop(logger);
+        let head = verify_audit_log(&AuditLog::read(&path).unwrap(), &key.verifying_key(), None).unwrap();
+        assert_eq!(head.events, 2);
+    }
+}
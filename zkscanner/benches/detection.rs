@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use zkscanner::{
+    encrypt_note, scan_wallet_notes_with_logger, AuditEvent, EncryptedNote, ExternalLogger,
+    NotePayload, SpendingKey, Wallet,
+};
+
+const NOTES: usize = 1_000_000;
+
+struct NoLogger;
+impl ExternalLogger for NoLogger {
+    fn log_event(&self, _event: &AuditEvent) {}
+}
+
+/// A wallet of `NOTES` notes, none of which belong to its single view key.
+fn foreign_wallet(pass_detection: bool) -> Wallet {
+    let ours = SpendingKey([1u8; 32]).incoming_view_key();
+    let theirs = SpendingKey([2u8; 32]).incoming_view_key();
+    let payload = NotePayload { amount: 1, recipient: "bench".to_string(), memo: None, ..NotePayload::default() };
+    // Notes sent to our own address with a foreign key commitment pass detection, so the scanner
+    // also derives a note key for each, as it did before notes carried detection tags.
+    let recipient = if pass_detection { &ours } else { &theirs };
+    let mut note = encrypt_note(&payload, &recipient.address_for("bench"), "bench", None).unwrap();
+    note.key_commitment = [0u8; 32];
+    let notes: Vec<EncryptedNote> = (0..NOTES).map(|_| note.clone()).collect();
+    Wallet { view_keys: vec![ours], notes }
+}
+
+fn bench_detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_1m_foreign_notes");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NOTES as u64));
+    for (name, pass_detection) in [("detection_tags", false), ("note_key_derivation", true)] {
+        let wallet = foreign_wallet(pass_detection);
+        group.bench_function(name, |b| {
+            b.iter(|| scan_wallet_notes_with_logger::<NoLogger>(&wallet, None, 0, None))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_detection);
+criterion_main!(benches);
@@ -0,0 +1,510 @@
+use serde::Deserialize;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::sync::mpsc as async_mpsc;
+use crate::decrypt_notes::{AuditEvent, ExternalLogger};
+
+/// Why a batch could not be written, and how far it got first.
+#[derive(Debug)]
+pub struct BatchError {
+    /// Leading events of the batch that were written before the failure. A retry resumes after
+    /// them, so they are not written twice.
+    pub written: usize,
+    pub error: io::Error,
+}
+
+impl From<io::Error> for BatchError {
+    fn from(error: io::Error) -> Self {
+        BatchError { written: 0, error }
+    }
+}
+
+/// Destination for batches of audit events written by a [`ChannelLogger`].
+pub trait AsyncSink: Send + 'static {
+    /// Write a whole batch. Errors of a transient kind (timeouts, refused or reset connections,
+    /// interrupted calls) are retried with backoff from the first unwritten event; any other
+    /// error drops the rest of the batch.
+    fn write_batch(&mut self, events: &[AuditEvent]) -> impl Future<Output = Result<(), BatchError>>;
+
+    /// Finish writing once the last batch is done, e.g. to sign a final checkpoint.
+    fn close(&mut self) -> impl Future<Output = io::Result<()>> {
+        async { Ok(()) }
+    }
+}
+
+/// Runs a synchronous logger on the channel's own thread, so a slow backend no longer holds up
+/// scanning. Failures the logger reports through
+/// [`try_log_event`](ExternalLogger::try_log_event) are retried like any sink's, starting from
+/// the event that failed, and the logger's [`close`](ExternalLogger::close) runs when the channel
+/// shuts down.
+pub struct LoggerSink<L>(pub L);
+
+impl<L: ExternalLogger + Send + 'static> AsyncSink for LoggerSink<L> {
+    async fn write_batch(&mut self, events: &[AuditEvent]) -> Result<(), BatchError> {
+        for (written, event) in events.iter().enumerate() {
+            self.0.try_log_event(event).map_err(|error| BatchError { written, error })?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.0.close()
+    }
+}
+
+/// What [`ChannelLogger`] does with an event when its buffer is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backpressure {
+    /// Discard the event and count it in [`ChannelLogger::dropped`]. Scanning never waits, at
+    /// the cost of gaps in the audit trail.
+    Drop,
+    /// Wait for room, so no event is lost but a slow sink slows scanning down again.
+    #[default]
+    Block,
+}
+
+/// Tuning for [`ChannelLogger`], read from the `[audit_queue]` section of the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QueueOptions {
+    /// Events buffered between the scanner and the sink.
+    pub capacity: usize,
+    /// Events written to the sink at once.
+    pub batch_size: usize,
+    /// Longest a partial batch waits before it is written anyway.
+    pub flush_interval_ms: u64,
+    /// Retries of a batch after a transient failure.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub retry_backoff_ms: u64,
+    pub backpressure: Backpressure,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        QueueOptions {
+            capacity: 4096,
+            batch_size: 64,
+            flush_interval_ms: 1000,
+            max_retries: 3,
+            retry_backoff_ms: 100,
+            backpressure: Backpressure::Block,
+        }
+    }
+}
+
+enum Message {
+    Event(AuditEvent),
+    Flush(Done),
+}
+
+/// Reply to a flush, to a blocked thread or to an awaiting task.
+enum Done {
+    Thread(mpsc::SyncSender<()>),
+    Task(tokio::sync::oneshot::Sender<()>),
+}
+
+impl From<mpsc::SyncSender<()>> for Done {
+    fn from(sender: mpsc::SyncSender<()>) -> Self {
+        Done::Thread(sender)
+    }
+}
+
+impl From<tokio::sync::oneshot::Sender<()>> for Done {
+    fn from(sender: tokio::sync::oneshot::Sender<()>) -> Self {
+        Done::Task(sender)
+    }
+}
+
+impl Done {
+    fn send(self) {
+        match self {
+            Done::Thread(sender) => drop(sender.send(())),
+            Done::Task(sender) => drop(sender.send(())),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    dropped: AtomicU64,
+    failed: AtomicU64,
+    /// Error the sink's `close` returned.
+    close_error: Mutex<Option<io::Error>>,
+}
+
+/// An [`ExternalLogger`] that hands events to a background thread instead of writing them
+/// inline.
+///
+/// The thread collects events into batches of `batch_size`, writes a partial batch once it has
+/// waited `flush_interval_ms`, and retries transient sink failures. Events still buffered are
+/// written before [`shutdown`](Self::shutdown) returns, which also happens when the logger is
+/// dropped, so nothing already accepted is lost by exiting normally.
+///
+/// With [`Backpressure::Block`], [`log_event`](ExternalLogger::log_event) and
+/// [`flush`](Self::flush) block the calling thread while the buffer is full, which is safe but
+/// stalls an async runtime's worker; async callers use [`log_event_async`](Self::log_event_async)
+/// and [`flush_async`](Self::flush_async) instead.
+pub struct ChannelLogger {
+    sender: Mutex<Option<async_mpsc::Sender<Message>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    backpressure: Backpressure,
+    counters: Arc<Counters>,
+}
+
+impl ChannelLogger {
+    pub fn spawn<S: AsyncSink>(sink: S, options: QueueOptions) -> io::Result<Self> {
+        let (sender, receiver) = async_mpsc::channel(options.capacity.max(1));
+        let counters = Arc::new(Counters::default());
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build()?;
+        let backpressure = options.backpressure;
+        let worker = thread::Builder::new().name("zkscanner-audit".to_string()).spawn({
+            let counters = counters.clone();
+            move || runtime.block_on(run(sink, receiver, options, &counters))
+        })?;
+        Ok(ChannelLogger {
+            sender: Mutex::new(Some(sender)),
+            worker: Mutex::new(Some(worker)),
+            backpressure,
+            counters,
+        })
+    }
+
+    /// Wait until every event logged so far has been written, or given up on.
+    pub fn flush(&self) {
+        let Some(sender) = self.sender() else { return };
+        let (done_tx, done_rx) = mpsc::sync_channel(1);
+        // Not `blocking_send`, which panics when called from within a tokio runtime.
+        if futures::executor::block_on(sender.send(Message::Flush(done_tx.into()))).is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+
+    /// [`flush`](Self::flush) without blocking the calling task's thread.
+    pub async fn flush_async(&self) {
+        let Some(sender) = self.sender() else { return };
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        if sender.send(Message::Flush(done_tx.into())).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+
+    /// Log an event from async code. With [`Backpressure::Block`] it waits for room without
+    /// blocking the thread.
+    pub async fn log_event_async(&self, event: &AuditEvent) {
+        let sent = match self.sender() {
+            Some(sender) => {
+                let message = Message::Event(event.clone());
+                match self.backpressure {
+                    Backpressure::Drop => sender.try_send(message).is_ok(),
+                    Backpressure::Block => sender.send(message).await.is_ok(),
+                }
+            }
+            None => false,
+        };
+        if !sent {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Write out everything still buffered and stop the background thread. Events logged
+    /// afterwards are counted as dropped.
+    pub fn shutdown(&self) {
+        drop(self.sender.lock().unwrap_or_else(|e| e.into_inner()).take());
+        if let Some(worker) = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = worker.join();
+        }
+    }
+
+    /// Events discarded because the buffer was full or the logger was shut down.
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Events the sink failed to write, after retries.
+    pub fn failed(&self) -> u64 {
+        self.counters.failed.load(Ordering::Relaxed)
+    }
+
+    fn sender(&self) -> Option<async_mpsc::Sender<Message>> {
+        self.sender.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ExternalLogger for ChannelLogger {
+    /// Shut down, which closes the sink, then fail if any event was dropped or could not be
+    /// written, or if closing the sink failed.
+    fn close(&self) -> io::Result<()> {
+        self.shutdown();
+        let close_error = self.counters.close_error.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match (self.dropped(), self.failed(), close_error) {
+            (0, 0, None) => Ok(()),
+            (0, 0, Some(e)) => Err(e),
+            (dropped, failed, e) => Err(io::Error::other(format!(
+                "{} audit events dropped and {} failed to write{}",
+                dropped,
+                failed,
+                e.map(|e| format!(", then closing failed: {}", e)).unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn log_event(&self, event: &AuditEvent) {
+        let sent = self.sender().is_some_and(|sender| {
+            let message = Message::Event(event.clone());
+            match self.backpressure {
+                Backpressure::Drop => sender.try_send(message).is_ok(),
+                // Not `blocking_send`, which panics when called from within a tokio runtime.
+                Backpressure::Block => futures::executor::block_on(sender.send(message)).is_ok(),
+            }
+        });
+        if !sent {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for ChannelLogger {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+async fn run<S: AsyncSink>(
+    mut sink: S,
+    mut receiver: async_mpsc::Receiver<Message>,
+    options: QueueOptions,
+    counters: &Counters,
+) {
+    let batch_size = options.batch_size.max(1);
+    let flush_interval = Duration::from_millis(options.flush_interval_ms);
+    let mut batch = Vec::with_capacity(batch_size);
+    loop {
+        let message = if batch.is_empty() {
+            receiver.recv().await
+        } else {
+            match tokio::time::timeout(flush_interval, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    write(&mut sink, &mut batch, &options, counters).await;
+                    continue;
+                }
+            }
+        };
+        match message {
+            Some(Message::Event(event)) => {
+                batch.push(event);
+                if batch.len() >= batch_size {
+                    write(&mut sink, &mut batch, &options, counters).await;
+                }
+            }
+            Some(Message::Flush(done)) => {
+                write(&mut sink, &mut batch, &options, counters).await;
+                done.send();
+            }
+            None => {
+                write(&mut sink, &mut batch, &options, counters).await;
+                if let Err(e) = sink.close().await {
+                    eprintln!("[audit] failed to close the audit log: {}", e);
+                    *counters.close_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(
+        error.kind(),
+        Interrupted | WouldBlock | TimedOut | ConnectionRefused | ConnectionReset | ConnectionAborted | NotConnected | BrokenPipe
+    )
+}
+
+/// Write and clear `batch`, retrying transient failures from the first unwritten event. A sink
+/// has no way to fail the scan, so events that cannot be written are reported on stderr and
+/// counted.
+async fn write<S: AsyncSink>(sink: &mut S, batch: &mut Vec<AuditEvent>, options: &QueueOptions, counters: &Counters) {
+    if batch.is_empty() {
+        return;
+    }
+    let mut backoff = Duration::from_millis(options.retry_backoff_ms);
+    let mut attempt = 0;
+    let mut start = 0;
+    loop {
+        match sink.write_batch(&batch[start..]).await {
+            Ok(()) => break,
+            Err(BatchError { written, error }) => {
+                start = (start + written).min(batch.len());
+                if attempt < options.max_retries && is_transient(&error) {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    continue;
+                }
+                let lost = batch.len() - start;
+                eprintln!("[audit] dropping {} events after {} attempts: {}", lost, attempt + 1, error);
+                counters.failed.fetch_add(lost as u64, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+    batch.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::EventDetails;
+
+    /// Records batches, failing the first `failures` writes with `kind`.
+    struct FlakySink {
+        batches: Arc<Mutex<Vec<usize>>>,
+        failures: u32,
+        kind: io::ErrorKind,
+    }
+
+    impl AsyncSink for FlakySink {
+        async fn write_batch(&mut self, events: &[AuditEvent]) -> Result<(), BatchError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::from(self.kind).into());
+            }
+            self.batches.lock().unwrap().push(events.len());
+            Ok(())
+        }
+    }
+
+    fn event(i: u64) -> AuditEvent {
+        AuditEvent {
+            timestamp: i as i64,
+            action: "decryption_attempt".to_string(),
+            details: EventDetails::new().with("metadata", format!("note{}", i)),
+        }
+    }
+
+    fn options(backpressure: Backpressure) -> QueueOptions {
+        QueueOptions {
+            capacity: 16,
+            batch_size: 4,
+            flush_interval_ms: 60_000,
+            retry_backoff_ms: 1,
+            backpressure,
+            ..QueueOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_batches_retries_and_flushes_on_shutdown() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink { batches: batches.clone(), failures: 2, kind: io::ErrorKind::ConnectionRefused };
+        let logger = ChannelLogger::spawn(sink, options(Backpressure::Block)).unwrap();
+        for i in 0..10 {
+            logger.log_event(&event(i));
+        }
+        logger.flush();
+        assert_eq!(*batches.lock().unwrap(), vec![4, 4, 2]);
+        logger.log_event(&event(10));
+        drop(logger);
+        assert_eq!(*batches.lock().unwrap(), vec![4, 4, 2, 1]);
+
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink { batches: batches.clone(), failures: 1, kind: io::ErrorKind::PermissionDenied };
+        let logger = ChannelLogger::spawn(sink, options(Backpressure::Block)).unwrap();
+        for i in 0..6 {
+            logger.log_event(&event(i));
+        }
+        logger.shutdown();
+        assert_eq!(logger.failed(), 4);
+        assert_eq!(*batches.lock().unwrap(), vec![2]);
+        logger.log_event(&event(6));
+        assert_eq!(logger.dropped(), 1);
+        assert_eq!(logger.close().unwrap_err().to_string(), "1 audit events dropped and 4 failed to write");
+    }
+
+    #[test]
+    fn test_logger_sink_retries_failed_writes() {
+        /// Fails once on the event at `fail_at`, and fails to close if `close_fails`.
+        struct Flaky {
+            written: Arc<Mutex<Vec<i64>>>,
+            fail_at: Mutex<Option<i64>>,
+            close_fails: bool,
+        }
+        impl ExternalLogger for Flaky {
+            fn log_event(&self, event: &AuditEvent) {
+                let _ = self.try_log_event(event);
+            }
+
+            fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+                if *self.fail_at.lock().unwrap() == Some(event.timestamp) {
+                    *self.fail_at.lock().unwrap() = None;
+                    return Err(io::Error::from(io::ErrorKind::TimedOut));
+                }
+                self.written.lock().unwrap().push(event.timestamp);
+                Ok(())
+            }
+
+            fn close(&self) -> io::Result<()> {
+                match self.close_fails {
+                    true => Err(io::Error::other("final checkpoint not written")),
+                    false => Ok(()),
+                }
+            }
+        }
+        for close_fails in [false, true] {
+            let written = Arc::new(Mutex::new(Vec::new()));
+            let sink = LoggerSink(Flaky { written: written.clone(), fail_at: Mutex::new(Some(1)), close_fails });
+            let logger = ChannelLogger::spawn(sink, options(Backpressure::default())).unwrap();
+            for i in 0..3 {
+                logger.log_event(&event(i));
+            }
+            let closed = logger.close();
+            // The retry resumes at the failed event instead of writing event 0 again.
+            assert_eq!(*written.lock().unwrap(), vec![0, 1, 2]);
+            assert_eq!(closed.is_err(), close_fails);
+        }
+    }
+
+    #[test]
+    fn test_blocking_logger_is_usable_from_async_code() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink { batches: batches.clone(), failures: 0, kind: io::ErrorKind::Other };
+        let logger = ChannelLogger::spawn(sink, options(Backpressure::Block)).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            logger.log_event(&event(0));
+            logger.log_event_async(&event(1)).await;
+            logger.flush_async().await;
+            logger.flush();
+        });
+        assert_eq!(*batches.lock().unwrap(), vec![2]);
+        logger.close().unwrap();
+    }
+
+    #[test]
+    fn test_drop_policy_never_blocks_the_scanner() {
+        struct Stalled(Arc<Mutex<()>>);
+        impl AsyncSink for Stalled {
+            async fn write_batch(&mut self, _events: &[AuditEvent]) -> Result<(), BatchError> {
+                drop(self.0.lock().unwrap());
+                Ok(())
+            }
+        }
+        let gate = Arc::new(Mutex::new(()));
+        let held = gate.lock().unwrap();
+        let logger = ChannelLogger::spawn(Stalled(gate.clone()), QueueOptions { batch_size: 1, ..options(Backpressure::Drop) }).unwrap();
+        for i in 0..100 {
+            logger.log_event(&event(i));
+        }
+        assert!(logger.dropped() > 0);
+        drop(held);
+        logger.shutdown();
+        assert_eq!(logger.failed(), 0);
+    }
+}
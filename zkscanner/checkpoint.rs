@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use crate::decrypt_notes::{decrypt_note, scan_keys, DecryptedNote, EncryptedNote, ExternalLogger, ScanStats, Wallet};
+use crate::error::NoteError;
+use crate::keys::IncomingViewKey;
+use crate::redact::RedactionPolicy;
+
+/// How far one view key has scanned a wallet's append-only note list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyCheckpoint {
+    /// Index of the first note this key has not scanned yet.
+    pub next_index: u64,
+    /// Hash chain over the notes before `next_index`, so a resumed scan can tell that the notes
+    /// it skips are the ones it saw last time.
+    #[serde(with = "hex")]
+    pub range_hash: [u8; 32],
+    /// Hash chain over the notes before `next_index - 1`, so a resumed scan can check the last
+    /// note it saw without re-hashing everything before it.
+    #[serde(default, with = "hex")]
+    pub prev_hash: [u8; 32],
+}
+
+impl KeyCheckpoint {
+    /// Whether this checkpoint can resume a scan of `notes`: they are at least as many as were
+    /// scanned, and the last scanned note is unchanged.
+    fn resumes(&self, notes: &[EncryptedNote]) -> Option<usize> {
+        let start = usize::try_from(self.next_index).ok().filter(|&start| start <= notes.len())?;
+        let last_ok = match start.checked_sub(1) {
+            None => self.range_hash == [0u8; 32],
+            Some(last) => chain_step(&self.prev_hash, &notes[last]) == self.range_hash,
+        };
+        last_ok.then_some(start)
+    }
+}
+
+/// Scan progress of every view key, persisted by the caller between syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCheckpoint {
+    /// Keyed by the view key's [fingerprint](IncomingViewKey::fingerprint), never by the key
+    /// itself.
+    pub keys: BTreeMap<String, KeyCheckpoint>,
+}
+
+impl ScanCheckpoint {
+    pub fn get(&self, view_key: &IncomingViewKey) -> Option<&KeyCheckpoint> {
+        self.keys.get(&view_key.fingerprint())
+    }
+}
+
+fn chain_step(prev: &[u8; 32], note: &EncryptedNote) -> [u8; 32] {
+    Sha256::new().chain_update(prev).chain_update(note.hash()).finalize().into()
+}
+
+/// `chain[i]` is the range hash of `notes[..i]` appended to a range whose hash is `prefix`.
+fn range_hashes(prefix: [u8; 32], notes: &[EncryptedNote]) -> Vec<[u8; 32]> {
+    let mut chain = Vec::with_capacity(notes.len() + 1);
+    chain.push(prefix);
+    for note in notes {
+        chain.push(chain_step(&chain[chain.len() - 1], note));
+    }
+    chain
+}
+
+/// Scan only the notes each view key has not seen yet, and advance `checkpoint` past them.
+///
+/// `wallet.notes` is treated as an append-only log, e.g. notes in indexer order. A key without a
+/// checkpoint, such as one just added to the wallet, is backfilled from the first note. If the
+/// notes a key already scanned no longer hash to its checkpoint, the scan fails with
+/// [`NoteError::CheckpointMismatch`] rather than silently skipping changed notes; start that key
+/// over with a fresh checkpoint. The checkpoint is only advanced when the whole scan succeeds.
+///
+/// Only the notes after the earliest checkpoint are hashed, so a sync costs as much as the notes
+/// appended since, not the whole history. What is checked is that the list still reaches every
+/// checkpoint, that the last note each key scanned is unchanged, and that all keys agree on the
+/// notes between their checkpoints. Notes further back than the earliest checkpoint are not
+/// read again, so rewriting them goes unnoticed. The checkpoint itself is trusted: its
+/// `prev_hash` is taken as stored, so it only guards against changed notes, not an edited
+/// checkpoint file. Likewise, a note listed twice is only returned once within one call; a copy
+/// of a note returned by an earlier sync is returned again.
+///
+/// There is no metadata filter here: a filtered-out note would still be marked as scanned.
+/// Audit events are redacted according to `policy`. As in
+/// [`scan_wallet_notes_with_stats`](crate::scan_wallet_notes_with_stats), a note listed twice is
+/// returned once, a malformed or tampered note is logged and skipped, and the stats count what
+/// each key unlocked among the notes it had not seen yet.
+pub fn scan_wallet_incremental<L: ExternalLogger>(
+    wallet: &Wallet,
+    checkpoint: &mut ScanCheckpoint,
+    now: i64,
+    logger: Option<&L>,
+    policy: &RedactionPolicy,
+) -> Result<(Vec<DecryptedNote>, ScanStats), NoteError> {
+    let logger = logger.map(|l| l as &dyn ExternalLogger);
+    let keys = scan_keys(&wallet.view_keys);
+    let mut starts = Vec::with_capacity(keys.len());
+    for key in &keys {
+        let resumed = match checkpoint.keys.get(&key.fingerprint) {
+            None => Some((0, [0u8; 32])),
+            Some(cp) => cp.resumes(&wallet.notes).map(|start| (start, cp.range_hash)),
+        };
+        let Some(resumed) = resumed else {
+            return Err(NoteError::CheckpointMismatch { key_id: key.fingerprint.clone() });
+        };
+        starts.push(resumed);
+    }
+    // Chain on from the earliest checkpoint; every later one must lie on that chain.
+    let end = wallet.notes.len();
+    let (base, prefix) = starts.iter().copied().min_by_key(|(start, _)| *start).unwrap_or((end, [0u8; 32]));
+    let chain = range_hashes(prefix, &wallet.notes[base..]);
+    let mut advanced = checkpoint.clone();
+    let mut results = Vec::new();
+    let mut stats = ScanStats::default();
+    let mut seen = HashSet::new();
+    for (key, (start, range_hash)) in keys.iter().zip(starts) {
+        let id = key.fingerprint.clone();
+        if chain[start - base] != range_hash {
+            return Err(NoteError::CheckpointMismatch { key_id: id });
+        }
+        let key_stats = stats.keys.entry(id.clone()).or_default();
+        for note in &wallet.notes[start..] {
+            let decrypted = match decrypt_note(key, note, None, now, logger, policy) {
+                Ok(Some(decrypted)) => decrypted,
+                Ok(None) => continue,
+                // Malformed and tampered notes were logged and are passed over for good.
+                Err(_) => {
+                    key_stats.rejected += 1;
+                    continue;
+                }
+            };
+            if !seen.insert(decrypted.note_id.clone()) {
+                key_stats.duplicates += 1;
+                continue;
+            }
+            key_stats.decrypted += 1;
+            key_stats.expired += decrypted.expired as u64;
+            results.push(decrypted);
+        }
+        if start < end {
+            let (range_hash, prev_hash) = (chain[end - base], chain[end - base - 1]);
+            advanced.keys.insert(id, KeyCheckpoint { next_index: end as u64, range_hash, prev_hash });
+        } else {
+            advanced.keys.entry(id).or_default();
+        }
+    }
+    *checkpoint = advanced;
+    Ok((results, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::{AuditEvent, NotePayload};
+    use crate::encrypt::encrypt_note;
+    use crate::keys::SpendingKey;
+
+    struct NoLogger;
+    impl ExternalLogger for NoLogger {
+        fn log_event(&self, _event: &AuditEvent) {}
+    }
+
+    fn note(view_key: &IncomingViewKey, amount: u64) -> EncryptedNote {
+        let payload = NotePayload { amount, recipient: "alice".to_string(), memo: None, ..NotePayload::default() };
+        encrypt_note(&payload, &view_key.address_for("alice"), &format!("note{}", amount), None).unwrap()
+    }
+
+    fn amounts(found: &[DecryptedNote]) -> Vec<u64> {
+        found.iter().map(|n| n.parsed.amount).collect()
+    }
+
+    #[test]
+    fn test_resume_scans_only_new_notes_and_backfills_new_keys() {
+        let first = SpendingKey([1u8; 32]).incoming_view_key();
+        let second = SpendingKey([2u8; 32]).incoming_view_key();
+        let mut wallet = Wallet {
+            view_keys: vec![first.clone()],
+            notes: vec![note(&first, 1), note(&second, 2)],
+        };
+        let mut checkpoint = ScanCheckpoint::default();
+        let (found, _) = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default()).unwrap();
+        assert_eq!(amounts(&found), vec![1]);
+        assert_eq!(checkpoint.get(&first).unwrap().next_index, 2);
+
+        wallet.notes.push(note(&first, 3));
+        wallet.view_keys.push(second.clone());
+        let (found, stats) = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default()).unwrap();
+        assert_eq!(amounts(&found), vec![3, 2]);
+        assert_eq!(stats.keys[&first.fingerprint()].decrypted, 1);
+        assert_eq!(stats.keys[&second.fingerprint()].decrypted, 1);
+
+        let (found, _) = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default()).unwrap();
+        assert!(found.is_empty());
+        assert_eq!(checkpoint.get(&second).unwrap().next_index, 3);
+    }
+
+    #[test]
+    fn test_rewritten_history_is_a_checkpoint_mismatch() {
+        let view_key = SpendingKey([3u8; 32]).incoming_view_key();
+        let mut wallet = Wallet {
+            view_keys: vec![view_key.clone()],
+            notes: vec![note(&view_key, 1), note(&view_key, 2)],
+        };
+        let mut checkpoint = ScanCheckpoint::default();
+        scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default()).unwrap();
+        let saved = checkpoint.clone();
+
+        // The note hash covers the detection tag and key commitment too.
+        wallet.notes[1].key_commitment = [0u8; 32];
+        let result = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default());
+        assert!(matches!(result, Err(NoteError::CheckpointMismatch { .. })));
+
+        wallet.notes[1] = note(&view_key, 5);
+        let result = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default());
+        assert!(matches!(result, Err(NoteError::CheckpointMismatch { .. })));
+        assert_eq!(checkpoint, saved);
+
+        wallet.notes.truncate(1);
+        let result = scan_wallet_incremental::<NoLogger>(&wallet, &mut checkpoint, 0, None, &RedactionPolicy::default());
+        assert!(matches!(result, Err(NoteError::CheckpointMismatch { .. })));
+    }
+}
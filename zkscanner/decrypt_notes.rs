@@ -1,115 +1,486 @@
 use aes_gcm::{Aes256Gcm, Key, Nonce};
-use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::aead::{Aead, NewAead, Payload};
 use serde::{Deserialize, Serialize};
-use chrono::{Utc, DateTime, TimeZone};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use crate::error::NoteError;
+use crate::payload::decode_payload;
+use crate::redact::{EventDetails, RedactionPolicy};
+use crate::keys::{key_commitment, IncomingViewKey, PaymentAddress, DETECTION_TAG_LEN};
 
-// TODO: Add a way to get the view key from the note
-// TODO: Add a way to get the note id from the note
-// TODO: Add a way to get the note amount from the note
-// TODO: Add a way to get the note recipient from the note
-// TODO: Add a way to get the note memo from the note
-// TODO: Add a way to get the note expires_at from the note
-// TODO: Add a way to get the note created_at from the note
-// TODO: Add a way to get the note updated_at from the note
+/// Length of the AES-GCM authentication tag appended to every ciphertext.
+const TAG_LEN: usize = 16;
 
-// TODO: Add a way to get the note from the note
-// TODO: Connect to a real logging backend (e.g., syslog, cloud logging, or blockchain event logs)
+const NOTE_HASH_DOMAIN: &[u8] = b"CipherPay_note_hash";
 
+/// Associated data authenticated alongside a note's ciphertext, so its metadata, expiry and
+/// epoch cannot be swapped, edited or stripped without the note failing to decrypt. The
+/// metadata is length prefixed so the encoding stays unambiguous. The epoch is only appended
+/// when the note is to an epoch key.
+pub(crate) fn associated_data(metadata: &str, expires_at: Option<i64>, epoch: Option<u32>) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + metadata.len() + 13);
+    aad.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    aad.extend_from_slice(metadata.as_bytes());
+    match expires_at {
+        Some(ts) => {
+            aad.push(1);
+            aad.extend_from_slice(&ts.to_le_bytes());
+        }
+        None => aad.push(0),
+    }
+    if let Some(epoch) = epoch {
+        aad.extend_from_slice(&epoch.to_le_bytes());
+    }
+    aad
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncryptedNote {
+    #[serde(with = "hex")]
     pub ciphertext: Vec<u8>,
+    #[serde(with = "hex")]
     pub nonce: [u8; 12],
+    /// Short tag from the note's key agreement, checked before the note key is derived.
+    #[serde(with = "hex")]
+    pub detection_tag: [u8; DETECTION_TAG_LEN],
+    /// Sender's ephemeral public key; combined with a view key it yields the note key.
+    #[serde(with = "hex")]
+    pub ephemeral_pubkey: [u8; 32],
+    /// Commitment to the note key, so a recipient can tell tampering from a note meant for others.
+    #[serde(with = "hex")]
+    pub key_commitment: [u8; 32],
     pub metadata: String, // e.g., note id or tag
     pub expires_at: Option<i64>, // Unix timestamp (optional)
+    /// Epoch of the recipient's view key, for notes sent to an [`EpochRoot`](crate::EpochRoot)
+    /// address.
+    #[serde(default)]
+    pub epoch: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+/// Asset ID of the chain's native token, used when a payload names no asset.
+pub const NATIVE_ASSET: &str = "native";
+
+impl EncryptedNote {
+    /// Hash of everything the note carries on chain.
+    pub(crate) fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new()
+            .chain_update(NOTE_HASH_DOMAIN)
+            .chain_update((self.ciphertext.len() as u64).to_le_bytes())
+            .chain_update(&self.ciphertext)
+            .chain_update(self.nonce)
+            .chain_update(self.detection_tag)
+            .chain_update(self.ephemeral_pubkey)
+            .chain_update(self.key_commitment)
+            .chain_update((self.metadata.len() as u64).to_le_bytes())
+            .chain_update(self.metadata.as_bytes());
+        match self.expires_at {
+            Some(ts) => {
+                hasher.update([1u8]);
+                hasher.update(ts.to_le_bytes());
+            }
+            None => hasher.update([0u8]),
+        }
+        match self.epoch {
+            Some(epoch) => {
+                hasher.update([1u8]);
+                hasher.update(epoch.to_le_bytes());
+            }
+            None => hasher.update([0u8]),
+        }
+        hasher.finalize().into()
+    }
+
+    /// Identity of the note: the same note listed twice has the same id.
+    pub fn id(&self) -> String {
+        hex::encode(&self.hash()[..16])
+    }
+}
+
+/// Decrypted contents of a note.
+///
+/// Only `amount`, `recipient`, `memo` and `blinding` enter the note commitment the guests prove
+/// against; the other fields are informational and only as trustworthy as the sender.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct NotePayload {
     pub amount: u64,
     pub recipient: String,
     pub memo: Option<String>,
+    /// Commitment randomness chosen by the sender. Needed to recompute the note commitment and
+    /// nullifier; older notes without it cannot be tracked as spent.
+    #[serde(default, with = "hex_option")]
+    pub blinding: Option<[u8; 32]>,
+    /// Token the amount is denominated in.
+    #[serde(default = "native_asset")]
+    pub asset_id: String,
+    /// Where the recipient can send refunds or replies.
+    #[serde(default)]
+    pub sender: Option<PaymentAddress>,
+    /// Invoice or other payment reference this note settles.
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// Unix timestamp at which the sender created the note.
+    #[serde(default)]
+    pub created_at: Option<i64>,
+    /// Structured memo fields, e.g. `order_id` or `tax_id`, alongside the free-text memo.
+    #[serde(default)]
+    pub memo_fields: BTreeMap<String, String>,
+}
+
+pub(crate) fn native_asset() -> String {
+    NATIVE_ASSET.to_string()
+}
+
+impl Default for NotePayload {
+    fn default() -> Self {
+        NotePayload {
+            amount: 0,
+            recipient: String::new(),
+            memo: None,
+            blinding: None,
+            asset_id: native_asset(),
+            sender: None,
+            reference: None,
+            created_at: None,
+            memo_fields: BTreeMap::new(),
+        }
+    }
+}
+
+pub(crate) mod hex_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<[u8; 32]>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(bytes) => serializer.serialize_some(&hex::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<[u8; 32]>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| {
+                let mut out = [0u8; 32];
+                hex::decode_to_slice(s.trim_start_matches("0x"), &mut out).map_err(serde::de::Error::custom)?;
+                Ok(out)
+            })
+            .transpose()
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct DecryptedNote {
+    /// [`EncryptedNote::id`] of the note this was decrypted from.
+    pub note_id: String,
+    /// [Fingerprint](IncomingViewKey::fingerprint) of the view key that decrypted it.
+    pub view_key: String,
     pub plaintext: Vec<u8>,
     pub metadata: String,
-    pub parsed: Option<NotePayload>,
+    pub parsed: NotePayload,
+    pub expires_at: Option<i64>,
     pub expired: bool,
     pub audit_trail: Vec<AuditEvent>,
 }
 
+impl DecryptedNote {
+    pub fn amount(&self) -> u64 {
+        self.parsed.amount
+    }
+
+    pub fn asset_id(&self) -> &str {
+        &self.parsed.asset_id
+    }
+
+    pub fn recipient(&self) -> &str {
+        &self.parsed.recipient
+    }
+
+    pub fn sender(&self) -> Option<&PaymentAddress> {
+        self.parsed.sender.as_ref()
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.parsed.memo.as_deref()
+    }
+
+    pub fn memo_field(&self, key: &str) -> Option<&str> {
+        self.parsed.memo_fields.get(key).map(String::as_str)
+    }
+
+    /// Invoice or payment reference, for matching proofs of payment to invoices.
+    pub fn reference(&self) -> Option<&str> {
+        self.parsed.reference.as_deref()
+    }
+
+    pub fn created_at(&self) -> Option<i64> {
+        self.parsed.created_at
+    }
+
+    pub fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct AuditEvent {
     pub timestamp: i64,
     pub action: String,
-    pub details: String,
+    pub details: EventDetails,
 }
 
 pub struct Wallet {
-    pub view_keys: Vec<[u8; 32]>,
+    pub view_keys: Vec<IncomingViewKey>,
     pub notes: Vec<EncryptedNote>,
 }
 
+/// What one view key unlocked during a scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStats {
+    /// Notes this key decrypted, each counted once.
+    pub decrypted: u64,
+    /// How many of those had expired.
+    pub expired: u64,
+    /// Further copies of notes already decrypted, which were left out of the results.
+    pub duplicates: u64,
+    /// Notes addressed to this key that could not be read, reported as audit events and left
+    /// out of the results.
+    #[serde(default)]
+    pub rejected: u64,
+}
+
+/// Per-key results of a scan, keyed by view key fingerprint. Every scanned key has an entry,
+/// even if it unlocked nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanStats {
+    pub keys: BTreeMap<String, KeyStats>,
+}
+
 /// Trait for external logging integration
 pub trait ExternalLogger {
     fn log_event(&self, event: &AuditEvent);
+
+    /// [`log_event`](Self::log_event), returning a write failure instead of reporting it, so a
+    /// queue in front of the logger can retry. Loggers that cannot fail keep the default.
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        self.log_event(event);
+        Ok(())
+    }
+
+    /// Finish logging, writing out anything still buffered, and fail if any event was lost.
+    fn close(&self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 /// Scan all notes in a wallet, using all available view keys. Optionally filter by metadata substring.
+///
+/// Each note is encrypted under its own key, agreed between the sender's ephemeral key and the
+/// recipient's view key. A view key only tries notes of its own epoch. The key agreement also
+/// yields a short detection tag, so most notes meant for others are discarded with one hash,
+/// before a note key is derived. Notes with an invalid ephemeral key, or whose key commitment
+/// does not match, simply belong to someone else and are skipped. Metadata, expiry and epoch are
+/// authenticated as associated data, so a note addressed to us that then fails to decrypt has been
+/// tampered with, e.g. by a relay.
+///
+/// Anyone can post or relay a note, so no single note stops the scan and there is no error to
+/// return. A tampered note is logged as a `metadata_tampered` event; a note addressed to us that is
+/// malformed (a ciphertext too short to carry a tag) or whose payload cannot be parsed is logged as
+/// a `malformed_note` event. Both name the view key the note was addressed to. Either way it is
+/// counted in [`KeyStats::rejected`] and left out of the results.
+///
+/// Audit events follow the default [`RedactionPolicy`], which keeps decrypted content out of
+/// them; see [`scan_wallet_notes_with_policy`] to choose another.
 pub fn scan_wallet_notes_with_logger<L: ExternalLogger>(wallet: &Wallet, filter: Option<&str>, now: i64, logger: Option<&L>) -> Vec<DecryptedNote> {
+    scan_wallet_notes_with_policy(wallet, filter, now, logger, &RedactionPolicy::default())
+}
+
+/// [`scan_wallet_notes_with_logger`] with an explicit policy for what audit events may reveal.
+pub fn scan_wallet_notes_with_policy<L: ExternalLogger>(
+    wallet: &Wallet,
+    filter: Option<&str>,
+    now: i64,
+    logger: Option<&L>,
+    policy: &RedactionPolicy,
+) -> Vec<DecryptedNote> {
+    scan_wallet_notes_with_stats(wallet, filter, now, logger, policy).0
+}
+
+/// [`scan_wallet_notes_with_policy`], also reporting what each view key unlocked.
+///
+/// Every result records the view key that decrypted it. A note that appears more than once, or
+/// under a view key listed twice, is returned once.
+pub fn scan_wallet_notes_with_stats<L: ExternalLogger>(
+    wallet: &Wallet,
+    filter: Option<&str>,
+    now: i64,
+    logger: Option<&L>,
+    policy: &RedactionPolicy,
+) -> (Vec<DecryptedNote>, ScanStats) {
+    let logger = logger.map(|l| l as &dyn ExternalLogger);
     let mut results = Vec::new();
-    for key in &wallet.view_keys {
-        let key = Key::from_slice(key);
-        let cipher = Aes256Gcm::new(key);
+    let mut stats = ScanStats::default();
+    let mut seen = HashSet::new();
+    for key in scan_keys(&wallet.view_keys) {
+        let key_stats = stats.keys.entry(key.fingerprint.clone()).or_default();
         for note in &wallet.notes {
-            if let Some(f) = filter {
-                if !note.metadata.contains(f) {
+            let decrypted = match decrypt_note(&key, note, filter, now, logger, policy) {
+                Ok(Some(decrypted)) => decrypted,
+                Ok(None) => continue,
+                Err(_) => {
+                    key_stats.rejected += 1;
                     continue;
                 }
+            };
+            if !seen.insert(decrypted.note_id.clone()) {
+                key_stats.duplicates += 1;
+                continue;
             }
-            let nonce = Nonce::from_slice(&note.nonce);
-            if let Ok(plaintext) = cipher.decrypt(nonce, note.ciphertext.as_ref()) {
-                // Multi-format parsing: try JSON, then bincode
-                let parsed = serde_json::from_slice::<NotePayload>(&plaintext)
-                    .or_else(|_| bincode::deserialize::<NotePayload>(&plaintext).ok())
-                    .ok();
-                // Expiration check
-                let expired = note.expires_at.map_or(false, |ts| ts < now);
-                // Advanced audit trail
-                let mut audit_trail = vec![AuditEvent {
-                    timestamp: now,
-                    action: "decryption_attempt".to_string(),
-                    details: format!("meta={} expired={} parsed={:?}", note.metadata, expired, parsed),
-                }];
-                if expired {
-                    audit_trail.push(AuditEvent {
-                        timestamp: now,
-                        action: "note_expired".to_string(),
-                        details: note.metadata.clone(),
-                    });
-                }
-                if let Some(logger) = logger {
-                    for event in &audit_trail {
-                        logger.log_event(event);
-                    }
-                }
-                results.push(DecryptedNote {
-                    plaintext,
-                    metadata: note.metadata.clone(),
-                    parsed,
-                    expired,
-                    audit_trail,
-                });
+            key_stats.decrypted += 1;
+            key_stats.expired += decrypted.expired as u64;
+            results.push(decrypted);
+        }
+    }
+    (results, stats)
+}
+
+/// A view key prepared for scanning, with its fingerprint derived once up front.
+pub(crate) struct ScanKey {
+    pub(crate) view_key: IncomingViewKey,
+    pub(crate) fingerprint: String,
+}
+
+/// Prepare view keys for scanning, dropping repeats so no note is decrypted twice by one key.
+pub(crate) fn scan_keys(view_keys: &[IncomingViewKey]) -> Vec<ScanKey> {
+    let mut seen = HashSet::new();
+    view_keys
+        .iter()
+        .filter(|view_key| seen.insert((view_key.to_bytes(), view_key.epoch())))
+        .map(|view_key| ScanKey {
+            view_key: view_key.clone(),
+            fingerprint: view_key.fingerprint(),
+        })
+        .collect()
+}
+
+/// Try one note against one view key. Returns `Ok(None)` when the note is filtered out or
+/// belongs to someone else.
+///
+/// A note whose key commitment matches this key but that cannot be read is logged as an audit event
+/// before its error is returned, so callers can skip it and carry on.
+pub(crate) fn decrypt_note(
+    key: &ScanKey,
+    note: &EncryptedNote,
+    filter: Option<&str>,
+    now: i64,
+    logger: Option<&dyn ExternalLogger>,
+    policy: &RedactionPolicy,
+) -> Result<Option<DecryptedNote>, NoteError> {
+    if let Some(f) = filter {
+        if !note.metadata.contains(f) {
+            return Ok(None);
+        }
+    }
+    if note.epoch != key.view_key.epoch() {
+        return Ok(None);
+    }
+    // An ephemeral key that is not a point cannot have been agreed with any view key.
+    let Some(secret) = key.view_key.shared_secret(&note.ephemeral_pubkey) else {
+        return Ok(None);
+    };
+    if secret.detection_tag() != note.detection_tag {
+        return Ok(None);
+    }
+    let note_key = secret.note_key();
+    if key_commitment(&note_key) != note.key_commitment {
+        return Ok(None);
+    }
+    if note.ciphertext.len() < TAG_LEN {
+        return Err(malformed(key, note, "ciphertext", now, logger, policy));
+    }
+    let cipher = Aes256Gcm::new(Key::from_slice(&note_key));
+    let nonce = Nonce::from_slice(&note.nonce);
+    let aad = associated_data(&note.metadata, note.expires_at, note.epoch);
+    let plaintext = match cipher.decrypt(nonce, Payload { msg: &note.ciphertext, aad: &aad }) {
+        Ok(plaintext) => plaintext,
+        Err(_) => {
+            let event = AuditEvent {
+                timestamp: now,
+                action: "metadata_tampered".to_string(),
+                details: policy.metadata(EventDetails::new(), &note.metadata).with("view_key", &key.fingerprint),
+            };
+            if let Some(logger) = logger {
+                logger.log_event(&event);
             }
+            return Err(NoteError::TamperedMetadata { metadata: note.metadata.clone() });
+        }
+    };
+    let parsed = match decode_payload(&plaintext) {
+        Ok(parsed) => parsed,
+        Err(reason) => {
+            malformed(key, note, "payload", now, logger, policy);
+            return Err(NoteError::ParseFailure { metadata: note.metadata.clone(), reason });
+        }
+    };
+    // Expiration check
+    let expired = note.expires_at.is_some_and(|ts| ts < now);
+    // Advanced audit trail
+    let mut audit_trail = vec![AuditEvent {
+        timestamp: now,
+        action: "decryption_attempt".to_string(),
+        details: policy.payload(policy.metadata(EventDetails::new(), &note.metadata), &parsed).with("expired", expired),
+    }];
+    if expired {
+        audit_trail.push(AuditEvent {
+            timestamp: now,
+            action: "note_expired".to_string(),
+            details: policy.metadata(EventDetails::new(), &note.metadata),
+        });
+    }
+    if let Some(logger) = logger {
+        for event in &audit_trail {
+            logger.log_event(event);
         }
     }
-    results
+    Ok(Some(DecryptedNote {
+        note_id: note.id(),
+        view_key: key.fingerprint.clone(),
+        plaintext,
+        metadata: note.metadata.clone(),
+        parsed,
+        expires_at: note.expires_at,
+        expired,
+        audit_trail,
+    }))
+}
+
+/// Log a `malformed_note` event naming the field that could not be read.
+fn malformed(
+    key: &ScanKey,
+    note: &EncryptedNote,
+    field: &str,
+    now: i64,
+    logger: Option<&dyn ExternalLogger>,
+    policy: &RedactionPolicy,
+) -> NoteError {
+    if let Some(logger) = logger {
+        logger.log_event(&AuditEvent {
+            timestamp: now,
+            action: "malformed_note".to_string(),
+            details: policy
+                .metadata(EventDetails::new(), &note.metadata)
+                .with("view_key", &key.fingerprint)
+                .with("field", field),
+        });
+    }
+    NoteError::MalformedCiphertext { metadata: note.metadata.clone() }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use aes_gcm::aead::Aead;
-    use rand::RngCore;
+    use crate::encrypt::seal_note;
+    use crate::payload::{encode_payload, PayloadError, PayloadFormat};
+    use crate::keys::SpendingKey;
     use chrono::Utc;
     use std::sync::{Arc, Mutex};
 
@@ -122,27 +493,28 @@ mod tests {
         }
     }
 
+    /// Encrypt `plaintext` to `view_key`'s address for `counterparty`, as a sender would.
+    fn note_to(view_key: &IncomingViewKey, counterparty: &str, plaintext: &[u8], metadata: &str, expires_at: Option<i64>) -> EncryptedNote {
+        let address = view_key.address_for(counterparty);
+        seal_note(plaintext, &address, metadata, expires_at, &mut rand::thread_rng()).unwrap()
+    }
+
     #[test]
     fn test_multi_format_parsing_and_external_logging() {
-        let view_key = [42u8; 32];
-        let key = Key::from_slice(&view_key);
-        let cipher = Aes256Gcm::new(key);
-        let nonce = rand::random::<[u8; 12]>();
+        let view_key = SpendingKey([42u8; 32]).incoming_view_key();
         let payload = NotePayload {
             amount: 123,
             recipient: "bob".to_string(),
             memo: Some("hello".to_string()),
+            reference: Some("INV-1042".to_string()),
+            ..NotePayload::default()
         };
-        // Serialize as bincode
-        let plaintext = bincode::serialize(&payload).unwrap();
-        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_ref()).unwrap();
+        let plaintext = encode_payload(&payload, PayloadFormat::Bincode).unwrap();
         let now = Utc::now().timestamp();
-        let notes = vec![EncryptedNote {
-            ciphertext: ciphertext.clone(),
-            nonce,
-            metadata: "note1:payment".to_string(),
-            expires_at: Some(now - 10), // already expired
-        }];
+        let notes = vec![
+            note_to(&view_key, "alice", &plaintext, "note1:payment", Some(now - 10)), // already expired
+            note_to(&SpendingKey([7u8; 32]).incoming_view_key(), "alice", &plaintext, "note2:payment", None),
+        ];
         let wallet = Wallet {
             view_keys: vec![view_key],
             notes,
@@ -150,11 +522,127 @@ mod tests {
         let logger = TestLogger { events: Arc::new(Mutex::new(vec![])) };
         let found = scan_wallet_notes_with_logger(&wallet, Some("payment"), now, Some(&logger));
         assert_eq!(found.len(), 1);
-        assert_eq!(found[0].parsed, Some(payload));
+        assert_eq!(found[0].metadata, "note1:payment");
+        assert_eq!(found[0].parsed, payload);
+        assert_eq!(found[0].reference(), Some("INV-1042"));
+        assert_eq!(found[0].asset_id(), NATIVE_ASSET);
         assert!(found[0].expired);
         // Check audit trail and logger
         let events = logger.events.lock().unwrap();
         assert!(events.iter().any(|e| e.action == "decryption_attempt"));
         assert!(events.iter().any(|e| e.action == "note_expired"));
+        // By default nothing decrypted reaches the logger.
+        for event in events.iter() {
+            for (_, value) in event.details.iter() {
+                for secret in ["123", "bob", "hello", "INV-1042"] {
+                    assert!(!value.contains(secret), "{} leaked in {:?}", secret, event);
+                }
+            }
+        }
+        assert_eq!(events[0].details.get("metadata"), Some("note1:payment"));
+        assert_eq!(events[0].details.get("expired"), Some("true"));
+    }
+
+    #[test]
+    fn test_results_are_attributed_and_deduplicated() {
+        let alice = SpendingKey([1u8; 32]).incoming_view_key();
+        let bob = SpendingKey([2u8; 32]).incoming_view_key();
+        let carol = SpendingKey([3u8; 32]).incoming_view_key();
+        let to_alice = note_to(&alice, "dan", b"\x01\x02\x01{\"amount\":1,\"recipient\":\"alice\",\"memo\":null}", "a", None);
+        let to_bob = note_to(&bob, "dan", b"\x01\x02\x01{\"amount\":2,\"recipient\":\"bob\",\"memo\":null}", "b", Some(0));
+        let wallet = Wallet {
+            view_keys: vec![alice.clone(), bob.clone(), alice.clone(), carol.clone()],
+            notes: vec![to_alice.clone(), to_bob.clone(), to_alice.clone()],
+        };
+        let (found, stats) =
+            scan_wallet_notes_with_stats::<TestLogger>(&wallet, None, 10, None, &RedactionPolicy::default());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].view_key, alice.fingerprint());
+        assert_eq!(found[0].note_id, to_alice.id());
+        assert_eq!(found[1].view_key, bob.fingerprint());
+        assert_ne!(alice.fingerprint(), bob.fingerprint());
+
+        assert_eq!(stats.keys.len(), 3);
+        assert_eq!(stats.keys[&alice.fingerprint()], KeyStats { decrypted: 1, expired: 0, duplicates: 1, rejected: 0 });
+        assert_eq!(stats.keys[&bob.fingerprint()], KeyStats { decrypted: 1, expired: 1, duplicates: 0, rejected: 0 });
+        assert_eq!(stats.keys[&carol.fingerprint()], KeyStats::default());
+    }
+
+    #[test]
+    fn test_epoch_rotation_cuts_off_old_auditors() {
+        let root = SpendingKey([4u8; 32]).epoch_root();
+        let plaintext = b"\x01\x02\x01{\"amount\":1,\"recipient\":\"alice\",\"memo\":null}";
+        let notes = vec![
+            note_to(&root.epoch_key(0), "dan", plaintext, "epoch0", None),
+            note_to(&root.epoch_key(1), "dan", plaintext, "epoch1", None),
+            note_to(&SpendingKey([4u8; 32]).incoming_view_key(), "dan", plaintext, "legacy", None),
+        ];
+        assert_eq!(notes[1].epoch, Some(1));
+        let scan = |view_keys: Vec<IncomingViewKey>| -> Vec<String> {
+            let wallet = Wallet { view_keys, notes: notes.clone() };
+            let found = scan_wallet_notes_with_logger::<TestLogger>(&wallet, None, 0, None);
+            found.into_iter().map(|n| n.metadata).collect()
+        };
+        // An auditor handed the epoch 0 key keeps reading epoch 0 but nothing after the rotation.
+        let auditor = IncomingViewKey::from_epoch_bytes(0, root.epoch_key(0).to_bytes()).unwrap();
+        assert_eq!(scan(vec![auditor]), vec!["epoch0"]);
+        assert_eq!(scan(root.epoch_keys(0..2)), vec!["epoch0", "epoch1"]);
+        assert_eq!(scan(vec![SpendingKey([4u8; 32]).incoming_view_key()]), vec!["legacy"]);
+    }
+
+    #[test]
+    fn test_scan_skips_malformed_unparseable_and_tampered_notes() {
+        let view_key = SpendingKey([7u8; 32]).incoming_view_key();
+        let good = note_to(&view_key, "carol", b"\x01\x02\x01{\"amount\":5,\"recipient\":\"carol\",\"memo\":null}", "good", None);
+        let mut truncated = note_to(&view_key, "carol", b"\x01\x02\x01{}", "short", None);
+        truncated.ciphertext.truncate(3);
+        let garbage = note_to(&view_key, "carol", b"not a payload", "garbage", None);
+        // An ephemeral key that is not a point was agreed with nobody, and a malformed foreign
+        // note is discarded on its tag; neither is reported.
+        let mut bad_point = note_to(&view_key, "carol", b"\x01\x02\x01{}", "point", None);
+        bad_point.ephemeral_pubkey = [0xff; 32];
+        let mut foreign = note_to(&SpendingKey([8u8; 32]).incoming_view_key(), "carol", b"\x01\x02\x01{}", "foreign", None);
+        foreign.ciphertext.truncate(3);
+        let wallet = Wallet {
+            view_keys: vec![view_key.clone()],
+            notes: vec![truncated, garbage, foreign, bad_point, good],
+        };
+        let logger = TestLogger { events: Arc::new(Mutex::new(vec![])) };
+        let (found, stats) =
+            scan_wallet_notes_with_stats(&wallet, None, 0, Some(&logger), &RedactionPolicy::default());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata, "good");
+        assert_eq!(stats.keys[&view_key.fingerprint()].rejected, 2);
+        let rejected: Vec<(String, String)> = logger
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.action == "malformed_note")
+            .map(|e| (e.details.get("metadata").unwrap().to_string(), e.details.get("field").unwrap().to_string()))
+            .collect();
+        let expected = [("short", "ciphertext"), ("garbage", "payload")];
+        assert_eq!(rejected, expected.map(|(m, f)| (m.to_string(), f.to_string())));
+
+        let garbage = note_to(&view_key, "carol", b"not a payload", "garbage", None);
+        let key = &scan_keys(std::slice::from_ref(&view_key))[0];
+        let result = decrypt_note(key, &garbage, None, 0, None, &RedactionPolicy::default());
+        assert!(matches!(result, Err(NoteError::ParseFailure { reason: PayloadError::UnknownFormat(b'n'), .. })));
+
+        // A relay that edits one note's metadata only loses that note.
+        let plaintext = b"\x01\x02\x01{\"amount\":3,\"recipient\":\"carol\",\"memo\":null}";
+        let mut retagged = note_to(&view_key, "carol", plaintext, "note3:payment", None);
+        retagged.metadata = "note3:ignored".to_string();
+        let intact = note_to(&view_key, "carol", plaintext, "note4:payment", None);
+        let wallet = Wallet { view_keys: vec![view_key.clone()], notes: vec![retagged.clone(), intact] };
+        let logger = TestLogger { events: Arc::new(Mutex::new(vec![])) };
+        let found = scan_wallet_notes_with_logger(&wallet, None, 0, Some(&logger));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata, "note4:payment");
+        assert_eq!(logger.events.lock().unwrap()[0].action, "metadata_tampered");
+        assert_eq!(logger.events.lock().unwrap()[0].details.get("metadata"), Some("note3:ignored"));
+        assert_eq!(logger.events.lock().unwrap()[0].details.get("view_key"), Some(view_key.fingerprint().as_str()));
+        let result = decrypt_note(key, &retagged, None, 0, None, &RedactionPolicy::default());
+        assert!(matches!(result, Err(NoteError::TamperedMetadata { .. })));
     }
 }
@@ -0,0 +1,300 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use crate::decrypt_notes::{decrypt_note, scan_keys, AuditEvent, DecryptedNote, EncryptedNote, ExternalLogger};
+use crate::error::NoteError;
+use crate::keys::{IncomingViewKey, PaymentAddress};
+use crate::redact::{EventDetails, RedactionPolicy};
+
+const DELEGATION_DOMAIN: &[u8] = b"CipherPay_delegation";
+/// Layout of the signed message, bumped whenever a field is added so old signatures fail
+/// instead of silently covering different bytes.
+const DELEGATION_ENCODING_VERSION: u8 = 1;
+
+/// Which notes a delegated key may be used for. Unset limits allow everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelegationScope {
+    /// Notes whose metadata starts with this prefix, e.g. `"payroll:"`.
+    pub metadata_prefix: Option<String>,
+    /// Notes created at or after this Unix timestamp. Notes without `created_at` are then out of
+    /// scope.
+    pub created_from: Option<i64>,
+    /// Notes created at or before this Unix timestamp, with the same caveat.
+    pub created_until: Option<i64>,
+    /// Notes of at most this amount.
+    pub max_amount: Option<u64>,
+}
+
+impl DelegationScope {
+    /// `Err` names the first limit the note breaks.
+    fn check(&self, note: &DecryptedNote) -> Result<(), &'static str> {
+        if let Some(prefix) = &self.metadata_prefix {
+            if !note.metadata.starts_with(prefix.as_str()) {
+                return Err("metadata_prefix");
+            }
+        }
+        if self.created_from.is_some() || self.created_until.is_some() {
+            let created_at = note.created_at().ok_or("created_at")?;
+            let too_early = self.created_from.is_some_and(|t| created_at < t);
+            let too_late = self.created_until.is_some_and(|t| created_at > t);
+            if too_early || too_late {
+                return Err("created_at");
+            }
+        }
+        if self.max_amount.is_some_and(|max| note.amount() > max) {
+            return Err("max_amount");
+        }
+        Ok(())
+    }
+}
+
+/// An epoch view key handed to an auditor for a limited time and a limited set of notes.
+///
+/// Only the epoch is a cryptographic limit: the auditor can decrypt every note of that epoch
+/// and of no other. The window and scope are honoured by [`scan_delegated`], checked against the
+/// auditor's own clock, and nothing stops an auditor from ignoring them with other software.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delegation {
+    /// Key of one epoch of the wallet's [`EpochRoot`](crate::EpochRoot). The wallet's own view
+    /// key is never delegated, so rotating to a new epoch cuts the auditor off from future notes.
+    #[serde(with = "hex")]
+    pub view_key: [u8; 32],
+    pub epoch: u32,
+    /// Unix timestamps bounding when the key may be used, both inclusive.
+    pub valid_from: i64,
+    pub valid_until: i64,
+    #[serde(default)]
+    pub scope: DelegationScope,
+}
+
+/// A [`Delegation`] signed with the wallet's [`owner_key`](crate::SpendingKey::owner_key) for an
+/// address of the delegated epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedDelegation {
+    pub delegation: Delegation,
+    #[serde(with = "hex")]
+    pub signature: Vec<u8>,
+}
+
+/// The bytes a delegation is signed over: fixed-width little-endian fields in declaration order,
+/// optional limits behind a presence byte and the metadata prefix behind its length. The
+/// encoding is written out here rather than taken from a serializer, so it only changes along
+/// with [`DELEGATION_ENCODING_VERSION`].
+fn delegation_message(delegation: &Delegation) -> Vec<u8> {
+    let Delegation { view_key, epoch, valid_from, valid_until, scope } = delegation;
+    let mut message = DELEGATION_DOMAIN.to_vec();
+    message.push(DELEGATION_ENCODING_VERSION);
+    message.extend_from_slice(view_key);
+    message.extend_from_slice(&epoch.to_le_bytes());
+    message.extend_from_slice(&valid_from.to_le_bytes());
+    message.extend_from_slice(&valid_until.to_le_bytes());
+    match &scope.metadata_prefix {
+        Some(prefix) => {
+            message.push(1);
+            message.extend_from_slice(&(prefix.len() as u64).to_le_bytes());
+            message.extend_from_slice(prefix.as_bytes());
+        }
+        None => message.push(0),
+    }
+    put_optional(&mut message, scope.created_from.map(i64::to_le_bytes));
+    put_optional(&mut message, scope.created_until.map(i64::to_le_bytes));
+    put_optional(&mut message, scope.max_amount.map(u64::to_le_bytes));
+    message
+}
+
+fn put_optional<const N: usize>(message: &mut Vec<u8>, value: Option<[u8; N]>) {
+    match value {
+        Some(bytes) => {
+            message.push(1);
+            message.extend_from_slice(&bytes);
+        }
+        None => message.push(0),
+    }
+}
+
+impl SignedDelegation {
+    pub fn sign(delegation: Delegation, owner: &SigningKey) -> Self {
+        let signature = owner.sign(&delegation_message(&delegation)).to_bytes().to_vec();
+        SignedDelegation { delegation, signature }
+    }
+
+    /// Check the delegation against an address of the wallet, as published by
+    /// [`epoch_address`](crate::SpendingKey::epoch_address), and return the delegated key.
+    ///
+    /// The signature must be by the owner key the address commits to, and the delegated key must
+    /// be the one behind the address, so a key cannot be delegated by anyone who merely holds it.
+    pub fn verify(&self, address: &PaymentAddress) -> Result<IncomingViewKey, NoteError> {
+        let owner = address.owner.ok_or(NoteError::InvalidDelegation)?;
+        let owner = VerifyingKey::from_bytes(&owner).map_err(|_| NoteError::InvalidDelegation)?;
+        let signature = Signature::from_slice(&self.signature).map_err(|_| NoteError::InvalidDelegation)?;
+        owner
+            .verify(&delegation_message(&self.delegation), &signature)
+            .map_err(|_| NoteError::InvalidDelegation)?;
+        let view_key = IncomingViewKey::from_epoch_bytes(self.delegation.epoch, self.delegation.view_key)?;
+        let derived = view_key.address(address.diversifier);
+        if derived.pk_d != address.pk_d || derived.epoch != address.epoch {
+            return Err(NoteError::InvalidDelegation);
+        }
+        Ok(view_key)
+    }
+}
+
+/// Scan notes with a delegated view key, enforcing its validity window and scope.
+///
+/// The delegation must [`verify`](SignedDelegation::verify) against `address`, an address of
+/// the delegated epoch obtained from the wallet owner, and `now` must fall within its window; a
+/// scan outside the window is logged as a `delegation_expired` event and fails with
+/// [`NoteError::DelegationExpired`]. Notes the key decrypts but that fall outside the scope are
+/// logged as `out_of_scope` events, naming the limit they break, and left out of the results.
+///
+/// The window and scope are an honour system: `now` is the auditor's clock, and the key
+/// decrypts every note of its epoch with other software. Only the epoch bounds what an auditor
+/// can read.
+pub fn scan_delegated<L: ExternalLogger>(
+    delegation: &SignedDelegation,
+    address: &PaymentAddress,
+    notes: &[EncryptedNote],
+    now: i64,
+    logger: Option<&L>,
+    policy: &RedactionPolicy,
+) -> Result<Vec<DecryptedNote>, NoteError> {
+    let view_key = delegation.verify(address)?;
+    let logger = logger.map(|l| l as &dyn ExternalLogger);
+    let Delegation { valid_from, valid_until, scope, .. } = &delegation.delegation;
+    let fingerprint = view_key.fingerprint();
+    if now < *valid_from || now > *valid_until {
+        if let Some(logger) = logger {
+            logger.log_event(&AuditEvent {
+                timestamp: now,
+                action: "delegation_expired".to_string(),
+                details: EventDetails::new()
+                    .with("view_key", &fingerprint)
+                    .with("valid_from", valid_from)
+                    .with("valid_until", valid_until),
+            });
+        }
+        return Err(NoteError::DelegationExpired { valid_from: *valid_from, valid_until: *valid_until });
+    }
+    let keys = scan_keys(&[view_key]);
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    for note in notes {
+        let decrypted = match decrypt_note(&keys[0], note, None, now, logger, policy) {
+            Ok(Some(decrypted)) => decrypted,
+            Ok(None) | Err(_) => continue,
+        };
+        match scope.check(&decrypted) {
+            Ok(()) => {
+                if seen.insert(decrypted.note_id.clone()) {
+                    results.push(decrypted);
+                }
+            }
+            Err(limit) => {
+                if let Some(logger) = logger {
+                    logger.log_event(&AuditEvent {
+                        timestamp: now,
+                        action: "out_of_scope".to_string(),
+                        details: policy
+                            .metadata(EventDetails::new(), &note.metadata)
+                            .with("view_key", &fingerprint)
+                            .with("limit", limit),
+                    });
+                }
+            }
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::NotePayload;
+    use crate::encrypt::encrypt_note;
+    use crate::keys::SpendingKey;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TestLogger(Mutex<Vec<AuditEvent>>);
+    impl ExternalLogger for TestLogger {
+        fn log_event(&self, event: &AuditEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn note(view_key: &IncomingViewKey, metadata: &str, amount: u64, created_at: Option<i64>) -> EncryptedNote {
+        let payload = NotePayload { amount, recipient: "alice".to_string(), created_at, ..NotePayload::default() };
+        encrypt_note(&payload, &view_key.address_for("bob"), metadata, None).unwrap()
+    }
+
+    #[test]
+    fn test_delegated_scan_enforces_window_and_scope() {
+        let wallet = SpendingKey([5u8; 32]);
+        let view_key = wallet.epoch_root().epoch_key(3);
+        let address = wallet.epoch_address(3, "auditor");
+        let delegation = SignedDelegation::sign(
+            Delegation {
+                view_key: view_key.to_bytes(),
+                epoch: 3,
+                valid_from: 100,
+                valid_until: 200,
+                scope: DelegationScope {
+                    metadata_prefix: Some("payroll:".to_string()),
+                    created_from: Some(10),
+                    created_until: None,
+                    max_amount: Some(1000),
+                },
+            },
+            &wallet.owner_key(&address.diversifier),
+        );
+        let notes = vec![
+            note(&view_key, "payroll:march", 500, Some(20)),
+            note(&view_key, "rent:march", 500, Some(20)),
+            note(&view_key, "payroll:bonus", 5000, Some(20)),
+            note(&view_key, "payroll:old", 500, Some(5)),
+            note(&wallet.epoch_root().epoch_key(4), "payroll:next", 1, Some(20)),
+            note(&SpendingKey([6u8; 32]).epoch_root().epoch_key(3), "payroll:other", 1, Some(20)),
+        ];
+        let policy = RedactionPolicy::default();
+
+        let logger = TestLogger::default();
+        let found = scan_delegated(&delegation, &address, &notes, 150, Some(&logger), &policy).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata, "payroll:march");
+        assert_eq!(found[0].view_key, view_key.fingerprint());
+        let limits: Vec<String> = logger
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.action == "out_of_scope")
+            .map(|e| e.details.get("limit").unwrap().to_string())
+            .collect();
+        assert_eq!(limits, vec!["metadata_prefix", "max_amount", "created_at"]);
+
+        let logger = TestLogger::default();
+        let result = scan_delegated(&delegation, &address, &notes, 201, Some(&logger), &policy);
+        assert_eq!(result.err(), Some(NoteError::DelegationExpired { valid_from: 100, valid_until: 200 }));
+        assert_eq!(logger.0.lock().unwrap()[0].action, "delegation_expired");
+
+        let mut widened = delegation.clone();
+        widened.delegation.scope.max_amount = None;
+        let result = scan_delegated::<TestLogger>(&widened, &address, &notes, 150, None, &policy);
+        assert_eq!(result.err(), Some(NoteError::InvalidDelegation));
+        widened.delegation.scope = DelegationScope::default();
+        let message = delegation_message(&widened.delegation);
+        assert_eq!(message.len(), DELEGATION_DOMAIN.len() + 1 + 32 + 4 + 8 + 8 + 4);
+        assert_eq!(message[DELEGATION_DOMAIN.len()], DELEGATION_ENCODING_VERSION);
+        // Addresses of other wallets, of other epochs and without an owner key all fail.
+        let next = wallet.epoch_address(4, "auditor");
+        let resigned = SignedDelegation::sign(delegation.delegation.clone(), &wallet.owner_key(&next.diversifier));
+        for (delegation, address) in [
+            (&delegation, SpendingKey([7u8; 32]).epoch_address(3, "auditor")),
+            (&resigned, next),
+            (&delegation, view_key.address_for("auditor")),
+        ] {
+            let result = scan_delegated::<TestLogger>(delegation, &address, &notes, 150, None, &policy);
+            assert_eq!(result.err(), Some(NoteError::InvalidDelegation));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::{CryptoRng, RngCore};
+use crate::decrypt_notes::{associated_data, EncryptedNote, NotePayload};
+use crate::error::NoteError;
+use crate::payload::{encode_payload, PayloadFormat};
+use crate::keys::{key_commitment, PaymentAddress};
+
+/// Encrypt a note payload to a recipient's payment address.
+///
+/// The payload is wrapped in a versioned JSON envelope and encrypted under a fresh per-note key agreed with the
+/// recipient's view key, with the metadata and expiry bound as AEAD associated data. This is the
+/// canonical note format read by [`scan_wallet_notes_with_logger`](crate::scan_wallet_notes_with_logger).
+pub fn encrypt_note(
+    payload: &NotePayload,
+    recipient: &PaymentAddress,
+    metadata: &str,
+    expires_at: Option<i64>,
+) -> Result<EncryptedNote, NoteError> {
+    encrypt_note_with_rng(payload, recipient, metadata, expires_at, &mut rand::thread_rng())
+}
+
+/// [`encrypt_note`] with an explicit randomness source.
+pub fn encrypt_note_with_rng<R: RngCore + CryptoRng>(
+    payload: &NotePayload,
+    recipient: &PaymentAddress,
+    metadata: &str,
+    expires_at: Option<i64>,
+    rng: &mut R,
+) -> Result<EncryptedNote, NoteError> {
+    let plaintext = encode_payload(payload, PayloadFormat::Json).map_err(|_| NoteError::EncryptionFailure)?;
+    seal_note(&plaintext, recipient, metadata, expires_at, rng)
+}
+
+/// Encrypt raw plaintext bytes in the canonical note format.
+pub(crate) fn seal_note<R: RngCore + CryptoRng>(
+    plaintext: &[u8],
+    recipient: &PaymentAddress,
+    metadata: &str,
+    expires_at: Option<i64>,
+    rng: &mut R,
+) -> Result<EncryptedNote, NoteError> {
+    let secret = recipient.ephemeral_secret(rng)?;
+    let note_key = secret.note_key();
+    // Every note has its own key, so a random nonce can never repeat under the same key.
+    let mut nonce = [0u8; 12];
+    rng.fill_bytes(&mut nonce);
+    let cipher = Aes256Gcm::new(Key::from_slice(&note_key));
+    let aad = associated_data(metadata, expires_at, recipient.epoch);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+        .map_err(|_| NoteError::EncryptionFailure)?;
+    Ok(EncryptedNote {
+        ciphertext,
+        nonce,
+        ephemeral_pubkey: secret.ephemeral_pubkey,
+        detection_tag: secret.detection_tag(),
+        key_commitment: key_commitment(&note_key),
+        metadata: metadata.to_string(),
+        expires_at,
+        epoch: recipient.epoch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::{decrypt_note, scan_keys, scan_wallet_notes_with_logger, AuditEvent, ExternalLogger, Wallet};
+    use crate::redact::RedactionPolicy;
+    use crate::keys::SpendingKey;
+
+    struct NoLogger;
+    impl ExternalLogger for NoLogger {
+        fn log_event(&self, _event: &AuditEvent) {}
+    }
+
+    #[test]
+    fn test_encrypt_note_round_trips_through_scanner() {
+        let view_key = SpendingKey([5u8; 32]).incoming_view_key();
+        let payload = NotePayload {
+            amount: 250,
+            recipient: "vendor-x".to_string(),
+            asset_id: "usdc".to_string(),
+            reference: Some("INV-7".to_string()),
+            created_at: Some(40),
+            ..NotePayload::default()
+        };
+        let note = encrypt_note(&payload, &view_key.address_for("vendor-x"), "note7:invoice", Some(100)).unwrap();
+        let other = encrypt_note(&payload, &view_key.address_for("vendor-x"), "note8:invoice", None).unwrap();
+        assert_ne!(note.ephemeral_pubkey, other.ephemeral_pubkey);
+
+        let wallet = Wallet { view_keys: vec![view_key], notes: vec![note, other] };
+        let found = scan_wallet_notes_with_logger::<NoLogger>(&wallet, None, 50, None);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].parsed, payload);
+        assert_eq!(found[0].metadata, "note7:invoice");
+        assert!(!found[0].expired);
+    }
+
+    #[test]
+    fn test_metadata_and_expiry_are_bound_to_ciphertext() {
+        let view_key = SpendingKey([6u8; 32]).incoming_view_key();
+        let payload = NotePayload { amount: 1, recipient: "bob".to_string(), memo: None, ..NotePayload::default() };
+        let note = encrypt_note(&payload, &view_key.address_for("bob"), "note1:payment", Some(10)).unwrap();
+
+        let mut retagged = note.clone();
+        retagged.metadata = "note1:refund".to_string();
+        let mut unexpiring = note;
+        unexpiring.expires_at = None;
+        let key = &scan_keys(std::slice::from_ref(&view_key))[0];
+        for tampered in [retagged, unexpiring] {
+            let metadata = tampered.metadata.clone();
+            let result = decrypt_note(key, &tampered, None, 0, None, &RedactionPolicy::default());
+            assert_eq!(result.err(), Some(NoteError::TamperedMetadata { metadata }));
+            let wallet = Wallet { view_keys: vec![view_key.clone()], notes: vec![tampered] };
+            assert!(scan_wallet_notes_with_logger::<NoLogger>(&wallet, None, 0, None).is_empty());
+        }
+    }
+}
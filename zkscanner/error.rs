@@ -0,0 +1,73 @@
+use thiserror::Error;
+use crate::payload::PayloadError;
+
+/// Errors returned by the note scanners.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NoteError {
+    #[error("invalid view key")]
+    InvalidViewKey,
+    #[error("invalid payment address")]
+    InvalidAddress,
+    #[error("failed to encrypt note")]
+    EncryptionFailure,
+    #[error("duplicate note id")]
+    DuplicateNoteId,
+    #[error("malformed ciphertext in note '{metadata}'")]
+    MalformedCiphertext { metadata: String },
+    #[error("metadata or expiry of note '{metadata}' was tampered with")]
+    TamperedMetadata { metadata: String },
+    #[error("checkpoint for view key {key_id} does not match the wallet's notes")]
+    CheckpointMismatch { key_id: String },
+    #[error("failed to parse payload of note '{metadata}': {reason}")]
+    ParseFailure { metadata: String, reason: PayloadError },
+    #[error("delegation is not signed by the owner key of the address, or is for another key")]
+    InvalidDelegation,
+    #[error("delegation is only valid from {valid_from} until {valid_until}")]
+    DelegationExpired { valid_from: i64, valid_until: i64 },
+}
+
+/// Errors returned by the encrypted note store.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum StoreError {
+    #[error("wrong password for note store")]
+    WrongPassword,
+    #[error("note store database error: {0}")]
+    Database(String),
+    #[error("corrupt note store record: {0}")]
+    Corrupt(String),
+    #[error("no stored note with id {0}")]
+    NotFound(String),
+}
+
+impl From<sled::Error> for StoreError {
+    fn from(e: sled::Error) -> Self {
+        StoreError::Database(e.to_string())
+    }
+}
+
+/// Ways a hash-chained audit log can fail verification.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuditLogError {
+    #[error("expected audit event {expected}, found {found}")]
+    SequenceGap { expected: u64, found: u64 },
+    #[error("audit event {seq} does not link to the event before it")]
+    BrokenLink { seq: u64 },
+    #[error("audit event {seq} was edited")]
+    EditedEvent { seq: u64 },
+    #[error("checkpoint at {seq} does not match the chain")]
+    CheckpointMismatch { seq: u64 },
+    #[error("checkpoint at {seq} has an invalid signature")]
+    BadSignature { seq: u64 },
+    #[error("audit log does not end with a signed checkpoint after {events} events")]
+    MissingCheckpoint { events: u64 },
+    #[error("audit log holds {found} events, fewer than the {expected} already verified")]
+    Truncated { expected: u64, found: u64 },
+    #[error("audit log does not match the head already verified at {events} events")]
+    ForkedHead { events: u64 },
+    #[error("invalid checkpoint verifying key")]
+    InvalidKey,
+    #[error("audit log I/O error: {0}")]
+    Io(String),
+    #[error("malformed audit log record: {0}")]
+    Malformed(String),
+}
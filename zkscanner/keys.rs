@@ -0,0 +1,321 @@
+use crate::decrypt_notes::hex_option;
+use crate::error::NoteError;
+use crate::nullifier::{field_hash, NullifierKey};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hkdf::Hkdf;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+
+// Domain separators for every derivation in the key hierarchy.
+const IVK_DOMAIN: &[u8] = b"CipherPay_ivk";
+const NK_DOMAIN: &[u8] = b"CipherPay_nk";
+const DIVERSIFIER_DOMAIN: &[u8] = b"CipherPay_diversifier";
+const BASE_DOMAIN: &[u8] = b"CipherPay_gd";
+const NOTE_KEY_INFO: &[u8] = b"CipherPay_note_key";
+const KEY_COMMITMENT_DOMAIN: &[u8] = b"CipherPay_key_commitment";
+const DETECTION_TAG_DOMAIN: &[u8] = b"CipherPay_detection_tag";
+const OWNER_KEY_DOMAIN: &[u8] = b"CipherPay_owner_key";
+const EPOCH_ROOT_DOMAIN: &[u8] = b"CipherPay_epoch_root";
+const EPOCH_IVK_DOMAIN: &[u8] = b"CipherPay_epoch_ivk";
+const FINGERPRINT_DOMAIN: &[u8] = b"CipherPay_key_fingerprint";
+
+/// Length of the detection tag carried by every note. Four bytes leave a 2^-32 false-positive
+/// rate per note and key; false positives only cost one extra note key derivation.
+pub const DETECTION_TAG_LEN: usize = 4;
+
+/// Root secret of a wallet. It never leaves the wallet; view keys are derived from it.
+#[derive(Clone)]
+pub struct SpendingKey(pub [u8; 32]);
+
+impl SpendingKey {
+    pub fn incoming_view_key(&self) -> IncomingViewKey {
+        IncomingViewKey(Scalar::hash_from_bytes::<Sha512>(&[IVK_DOMAIN, &self.0[..]].concat()), None)
+    }
+
+    /// Root from which the view key of every epoch is derived.
+    pub fn epoch_root(&self) -> EpochRoot {
+        let digest = Sha256::new()
+            .chain_update(EPOCH_ROOT_DOMAIN)
+            .chain_update(self.0)
+            .finalize();
+        EpochRoot(digest.into())
+    }
+
+    /// Secret that turns a note commitment into its nullifier. It is needed to see which notes
+    /// have been spent, but cannot spend them.
+    pub fn nullifier_key(&self) -> NullifierKey {
+        NullifierKey(field_hash(&[NK_DOMAIN, &self.0[..]].concat()))
+    }
+
+    /// Ed25519 key the wallet owner signs delegations with, one per address so that the public
+    /// halves in [`epoch_address`](Self::epoch_address) do not link addresses together.
+    pub fn owner_key(&self, diversifier: &Diversifier) -> ed25519_dalek::SigningKey {
+        let seed = Sha256::new()
+            .chain_update(OWNER_KEY_DOMAIN)
+            .chain_update(self.0)
+            .chain_update(diversifier.0)
+            .finalize();
+        ed25519_dalek::SigningKey::from_bytes(&seed.into())
+    }
+
+    /// Address of an epoch key for one counterparty, committing to the owner key of that
+    /// address. An auditor checks delegations against such an address, which ties the signer to
+    /// the delegated key.
+    pub fn epoch_address(&self, epoch: u32, counterparty: &str) -> PaymentAddress {
+        let mut address = self.epoch_root().epoch_key(epoch).address_for(counterparty);
+        address.owner = Some(self.owner_key(&address.diversifier).verifying_key().to_bytes());
+        address
+    }
+}
+
+impl fmt::Debug for SpendingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpendingKey(..)")
+    }
+}
+
+/// Derives one view key per epoch, so view access can be shared for some epochs and revoked
+/// for later ones.
+///
+/// Each epoch key has its own addresses, and notes sent to them carry the epoch. Epoch keys are
+/// one-way hashes of the root, so an auditor given the keys of some epochs learns nothing about
+/// any other epoch: publishing addresses of a new epoch and having senders use them cuts every
+/// earlier auditor off from future notes, without touching notes they could already read. The
+/// root itself stays with the wallet owner.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EpochRoot(pub [u8; 32]);
+
+impl EpochRoot {
+    pub fn epoch_key(&self, epoch: u32) -> IncomingViewKey {
+        let input = [EPOCH_IVK_DOMAIN, &self.0[..], &epoch.to_le_bytes()[..]].concat();
+        IncomingViewKey(Scalar::hash_from_bytes::<Sha512>(&input), Some(epoch))
+    }
+
+    /// View keys of every epoch in `epochs`, e.g. all epochs so far for the owner's own scanner.
+    pub fn epoch_keys(&self, epochs: std::ops::Range<u32>) -> Vec<IncomingViewKey> {
+        epochs.map(|epoch| self.epoch_key(epoch)).collect()
+    }
+}
+
+impl fmt::Debug for EpochRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EpochRoot(..)")
+    }
+}
+
+/// Decrypts every note sent to any diversified address of a wallet, but cannot spend.
+///
+/// A key derived from an [`EpochRoot`] only decrypts notes of its own epoch; a key without an
+/// epoch only decrypts notes without one.
+#[derive(Clone, PartialEq, Eq)]
+pub struct IncomingViewKey(Scalar, Option<u32>);
+
+impl IncomingViewKey {
+    /// Parse a serialized key. Non-canonical and zero scalars are rejected.
+    pub fn from_bytes(bytes: [u8; 32]) -> Result<Self, NoteError> {
+        let scalar = Option::<Scalar>::from(Scalar::from_canonical_bytes(bytes))
+            .ok_or(NoteError::InvalidViewKey)?;
+        if scalar == Scalar::ZERO {
+            return Err(NoteError::InvalidViewKey);
+        }
+        Ok(IncomingViewKey(scalar, None))
+    }
+
+    /// Parse a serialized epoch key, as handed to an auditor together with its epoch.
+    pub fn from_epoch_bytes(epoch: u32, bytes: [u8; 32]) -> Result<Self, NoteError> {
+        Ok(IncomingViewKey(Self::from_bytes(bytes)?.0, Some(epoch)))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn epoch(&self) -> Option<u32> {
+        self.1
+    }
+
+    /// Short public identifier of this key, for attributing scan results and checkpoints to it.
+    /// It does not reveal the key. The epoch is part of it, since the same scalar under another
+    /// epoch decrypts other notes.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new()
+            .chain_update(FINGERPRINT_DOMAIN)
+            .chain_update(self.0.as_bytes());
+        if let Some(epoch) = self.1 {
+            hasher.update(epoch.to_le_bytes());
+        }
+        hex::encode(&hasher.finalize()[..16])
+    }
+
+    /// Payment address for an explicit diversifier.
+    pub fn address(&self, diversifier: Diversifier) -> PaymentAddress {
+        let pk_d = self.0 * diversified_base(&diversifier);
+        PaymentAddress {
+            diversifier,
+            pk_d: pk_d.compress().to_bytes(),
+            epoch: self.1,
+            owner: None,
+        }
+    }
+
+    /// Deterministic payment address for one counterparty, so each counterparty sees a different
+    /// address while the same view key still decrypts all of them.
+    pub fn address_for(&self, counterparty: &str) -> PaymentAddress {
+        let digest = Sha256::new()
+            .chain_update(DIVERSIFIER_DOMAIN)
+            .chain_update(self.0.as_bytes())
+            .chain_update(counterparty.as_bytes())
+            .finalize();
+        let mut diversifier = [0u8; 11];
+        diversifier.copy_from_slice(&digest[..11]);
+        self.address(Diversifier(diversifier))
+    }
+
+    /// Recipient side of the key agreement for a note's ephemeral public key, or `None` when the
+    /// ephemeral key is not a valid point.
+    pub(crate) fn shared_secret(&self, ephemeral_pubkey: &[u8; 32]) -> Option<SharedSecret> {
+        let epk = decompress(ephemeral_pubkey)?;
+        Some(SharedSecret { shared: self.0 * epk, ephemeral_pubkey: *ephemeral_pubkey })
+    }
+}
+
+impl fmt::Debug for IncomingViewKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IncomingViewKey(..)")
+    }
+}
+
+/// Selects one of the many payment addresses of an incoming view key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Diversifier(#[serde(with = "hex")] pub [u8; 11]);
+
+/// Where a sender encrypts notes to: a diversifier, the matching diversified public key and, for
+/// epoch keys, the epoch. Nothing in it is shared between the addresses of a view key, so two
+/// addresses cannot be linked to the same wallet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentAddress {
+    pub diversifier: Diversifier,
+    #[serde(with = "hex")]
+    pub pk_d: [u8; 32],
+    /// Epoch of the view key behind this address, copied into every note sent to it.
+    #[serde(default)]
+    pub epoch: Option<u32>,
+    /// Public half of the wallet's [`owner_key`](SpendingKey::owner_key) for this address, set
+    /// by [`SpendingKey::epoch_address`]. It differs per address like everything else here.
+    #[serde(default, with = "hex_option")]
+    pub owner: Option<[u8; 32]>,
+}
+
+impl PaymentAddress {
+    /// Sender side of the key agreement, under a fresh ephemeral key.
+    pub(crate) fn ephemeral_secret<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<SharedSecret, NoteError> {
+        let pk_d = decompress(&self.pk_d).ok_or(NoteError::InvalidAddress)?;
+        let esk = Scalar::random(rng);
+        let ephemeral_pubkey = (esk * diversified_base(&self.diversifier)).compress().to_bytes();
+        Ok(SharedSecret { shared: esk * pk_d, ephemeral_pubkey })
+    }
+}
+
+/// Outcome of the key agreement for one note, known only to its sender and to the recipient's
+/// view key.
+pub(crate) struct SharedSecret {
+    shared: RistrettoPoint,
+    pub(crate) ephemeral_pubkey: [u8; 32],
+}
+
+impl SharedSecret {
+    /// Short tag that lets the recipient discard notes meant for others before deriving a note
+    /// key. It comes from the per-note secret, so it reveals nothing to anyone without the view
+    /// key and is unrelated between notes, even to the same address.
+    pub(crate) fn detection_tag(&self) -> [u8; DETECTION_TAG_LEN] {
+        let digest = Sha256::new()
+            .chain_update(DETECTION_TAG_DOMAIN)
+            .chain_update(self.shared.compress().as_bytes())
+            .chain_update(self.ephemeral_pubkey)
+            .finalize();
+        let mut tag = [0u8; DETECTION_TAG_LEN];
+        tag.copy_from_slice(&digest[..DETECTION_TAG_LEN]);
+        tag
+    }
+
+    pub(crate) fn note_key(&self) -> [u8; 32] {
+        derive_note_key(&self.shared, &self.ephemeral_pubkey)
+    }
+}
+
+/// Diversified base point G_d. Both pk_d = ivk·G_d and epk = esk·G_d live on it, so
+/// ivk·epk = esk·pk_d without the recipient having to know which address was used.
+fn diversified_base(diversifier: &Diversifier) -> RistrettoPoint {
+    RistrettoPoint::hash_from_bytes::<Sha512>(&[BASE_DOMAIN, &diversifier.0[..]].concat())
+}
+
+fn decompress(bytes: &[u8; 32]) -> Option<RistrettoPoint> {
+    CompressedRistretto(*bytes).decompress()
+}
+
+fn derive_note_key(shared: &RistrettoPoint, ephemeral_pubkey: &[u8; 32]) -> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(Some(ephemeral_pubkey), shared.compress().as_bytes());
+    let mut key = [0u8; 32];
+    hk.expand(NOTE_KEY_INFO, &mut key).expect("32 bytes is a valid HKDF output length");
+    key
+}
+
+/// Public commitment to a note key. It lets a recipient tell a note that was not sent to them
+/// apart from one that was, but whose authenticated fields have been altered.
+pub(crate) fn key_commitment(note_key: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(KEY_COMMITMENT_DOMAIN)
+        .chain_update(note_key)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::scan_keys;
+
+    #[test]
+    fn test_sender_and_recipient_agree_on_note_key() {
+        let ivk = SpendingKey([1u8; 32]).incoming_view_key();
+        let other = SpendingKey([2u8; 32]).incoming_view_key();
+        let mut rng = rand::thread_rng();
+        for counterparty in ["alice", "bob"] {
+            let address = ivk.address_for(counterparty);
+            let sent = address.ephemeral_secret(&mut rng).unwrap();
+            let received = ivk.shared_secret(&sent.ephemeral_pubkey).unwrap();
+            assert_eq!(received.note_key(), sent.note_key());
+            assert_eq!(received.detection_tag(), sent.detection_tag());
+            assert_ne!(other.shared_secret(&sent.ephemeral_pubkey).unwrap().note_key(), sent.note_key());
+        }
+        let (alice, bob) = (ivk.address_for("alice"), ivk.address_for("bob"));
+        assert_ne!(alice.diversifier, bob.diversifier);
+        assert_ne!(alice.pk_d, bob.pk_d);
+    }
+
+    #[test]
+    fn test_view_key_round_trip_and_rejects_zero() {
+        let ivk = SpendingKey([3u8; 32]).incoming_view_key();
+        assert_eq!(IncomingViewKey::from_bytes(ivk.to_bytes()).unwrap(), ivk);
+        assert_eq!(IncomingViewKey::from_bytes([0u8; 32]), Err(NoteError::InvalidViewKey));
+
+        let epoch_key = SpendingKey([3u8; 32]).epoch_root().epoch_key(7);
+        assert_eq!(IncomingViewKey::from_epoch_bytes(7, epoch_key.to_bytes()).unwrap(), epoch_key);
+        assert_eq!(epoch_key.address_for("alice").epoch, Some(7));
+        assert_ne!(epoch_key, SpendingKey([3u8; 32]).epoch_root().epoch_key(8));
+        assert_ne!(epoch_key.to_bytes(), ivk.to_bytes());
+        let relabeled = IncomingViewKey::from_epoch_bytes(8, epoch_key.to_bytes()).unwrap();
+        assert_ne!(relabeled.fingerprint(), epoch_key.fingerprint());
+        assert_ne!(IncomingViewKey::from_bytes(epoch_key.to_bytes()).unwrap().fingerprint(), epoch_key.fingerprint());
+        assert_eq!(scan_keys(&[epoch_key.clone(), relabeled, epoch_key.clone()]).len(), 2);
+
+        let wallet = SpendingKey([3u8; 32]);
+        let address = wallet.epoch_address(7, "alice");
+        assert_eq!(address.pk_d, epoch_key.address_for("alice").pk_d);
+        assert_eq!(address.owner, Some(wallet.owner_key(&address.diversifier).verifying_key().to_bytes()));
+        assert_ne!(address.owner, wallet.epoch_address(7, "bob").owner);
+    }
+}
@@ -1,6 +1,36 @@
+mod audit_log;
+mod channel_logger;
+mod checkpoint;
 mod decrypt_notes;
+mod delegation;
+mod encrypt;
+mod error;
+mod keys;
+mod loggers;
+mod notes;
+mod nullifier;
+mod payload;
+mod redact;
+mod store;
+mod stream;
 
-pub use decrypt_notes::{EncryptedNote, DecryptedNote, NotePayload, AuditEvent, Wallet, ExternalLogger, scan_wallet_notes_with_logger};
+pub use audit_log::{verify_audit_log, verify_audit_log_file, AuditLog, AuditRecord, ChainHead, ChainedEvent, HashChainLogger, SignedCheckpoint};
+pub use channel_logger::{AsyncSink, Backpressure, BatchError, ChannelLogger, LoggerSink, QueueOptions};
+pub use checkpoint::{scan_wallet_incremental, KeyCheckpoint, ScanCheckpoint};
+pub use decrypt_notes::{NATIVE_ASSET, EncryptedNote, DecryptedNote, NotePayload, AuditEvent, Wallet, ExternalLogger, scan_wallet_notes_with_logger, scan_wallet_notes_with_policy, scan_wallet_notes_with_stats, KeyStats, ScanStats};
+pub use delegation::{scan_delegated, Delegation, DelegationScope, SignedDelegation};
+pub use encrypt::{encrypt_note, encrypt_note_with_rng};
+pub use error::{AuditLogError, NoteError, StoreError};
+pub use keys::{Diversifier, EpochRoot, IncomingViewKey, PaymentAddress, SpendingKey, DETECTION_TAG_LEN};
+pub use loggers::{format_rfc5424, BoxedLogger, JsonLinesLogger, LoggerConfig, ScannerConfig, TracingLogger};
+#[cfg(unix)]
+pub use loggers::SyslogLogger;
+pub use notes::{Note, scan_notes};
+pub use nullifier::{note_commitment, nullifier, track_spends, NullifierKey, SpendAnomaly, SpendReport, SpendStatus, TrackedNote};
+pub use payload::{decode_payload, encode_payload, PayloadError, PayloadFormat, PayloadType, CURRENT_VERSION};
+pub use redact::{EventDetails, FieldRedaction, RedactionPolicy};
+pub use store::{NoteQuery, NoteStore, StoredNote};
+pub use stream::{CancelToken, NoteScanner, ScanOptions, ScanResults, ScanStream, SharedLogger};
 
 /// Unified API for scanning and decrypting wallet notes with optional filtering and external logging.
 ///
@@ -12,9 +42,10 @@ pub use decrypt_notes::{EncryptedNote, DecryptedNote, NotePayload, AuditEvent, W
 ///
 /// # Returns
 /// A vector of decrypted notes with parsed payloads, expiration status, and audit trails.
+/// Notes that cannot be read are logged and skipped rather than failing the scan.
 #[inline]
-pub fn scan_notes_with_audit<'a, L: ExternalLogger>(
-    wallet: &'a Wallet,
+pub fn scan_notes_with_audit<L: ExternalLogger>(
+    wallet: &Wallet,
     filter: Option<&str>,
     now: i64,
     logger: Option<&L>,
@@ -0,0 +1,459 @@
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::audit_log::HashChainLogger;
+use crate::channel_logger::{ChannelLogger, LoggerSink, QueueOptions};
+use crate::decrypt_notes::{AuditEvent, ExternalLogger};
+use crate::redact::{EventDetails, RedactionPolicy};
+
+impl<L: ExternalLogger + ?Sized> ExternalLogger for Box<L> {
+    fn log_event(&self, event: &AuditEvent) {
+        (**self).log_event(event)
+    }
+
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        (**self).try_log_event(event)
+    }
+
+    fn close(&self) -> io::Result<()> {
+        (**self).close()
+    }
+}
+
+impl<L: ExternalLogger + ?Sized> ExternalLogger for Arc<L> {
+    fn log_event(&self, event: &AuditEvent) {
+        (**self).log_event(event)
+    }
+
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        (**self).try_log_event(event)
+    }
+
+    fn close(&self) -> io::Result<()> {
+        (**self).close()
+    }
+}
+
+/// A logger built from configuration.
+pub type BoxedLogger = Box<dyn ExternalLogger + Send + Sync>;
+
+/// Appends one JSON object per event to a file, rotating it once it reaches `max_bytes`.
+///
+/// Rotation renames `audit.jsonl` to `audit.jsonl.1`, shifting older files up by one and
+/// dropping anything past `max_files`. Write errors are reported on stderr, since a logger has
+/// no way to fail the scan; behind an `[audit_queue]` they are retried instead.
+pub struct JsonLinesLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Mutex<(File, u64)>,
+}
+
+impl JsonLinesLogger {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(JsonLinesLogger { path, max_bytes, max_files, file: Mutex::new((file, size)) })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> io::Result<File> {
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        File::create(&self.path)
+    }
+
+    fn write_line(&self, line: &[u8]) -> io::Result<()> {
+        let mut state = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if state.1 > 0 && state.1 + line.len() as u64 > self.max_bytes {
+            *state = (self.rotate()?, 0);
+        }
+        state.0.write_all(line)?;
+        state.1 += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl ExternalLogger for JsonLinesLogger {
+    fn log_event(&self, event: &AuditEvent) {
+        if let Err(e) = self.try_log_event(event) {
+            eprintln!("[audit] failed to write {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        let mut line = serde_json::to_vec(event).expect("audit events serialize to JSON");
+        line.push(b'\n');
+        self.write_line(&line)
+    }
+}
+
+/// Severity of an audit action in syslog terms: tampering is a warning, everything else is
+/// informational.
+fn severity(action: &str) -> u8 {
+    match action {
+        "metadata_tampered" => 4,
+        "note_expired" => 5,
+        _ => 6,
+    }
+}
+
+/// SD-ID of the structured data element carrying event details. 32473 is the private
+/// enterprise number RFC 5612 reserves for documentation.
+const SD_ID: &str = "zkaudit@32473";
+
+/// Event details as an RFC 5424 structured data element, or `-` when there are none.
+fn structured_data(details: &EventDetails) -> String {
+    if details.0.is_empty() {
+        return "-".to_string();
+    }
+    let mut out = format!("[{}", SD_ID);
+    for (key, value) in details.iter() {
+        // SD-NAMEs are printable ASCII without '=', ' ', ']' or '"', at most 32 characters.
+        let name: String = key
+            .chars()
+            .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+            .take(32)
+            .collect();
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+        out.push_str(&format!(" {}=\"{}\"", name, value));
+    }
+    out.push(']');
+    out
+}
+
+/// Format an event as an RFC 5424 syslog message, with its details as structured data.
+pub fn format_rfc5424(event: &AuditEvent, facility: u8, hostname: &str, app_name: &str) -> String {
+    let timestamp = Utc
+        .timestamp_opt(event.timestamp, 0)
+        .single()
+        .map_or_else(|| "-".to_string(), |t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    format!(
+        "<{}>1 {} {} {} {} {} {}",
+        facility as u16 * 8 + severity(&event.action) as u16,
+        timestamp,
+        hostname,
+        app_name,
+        std::process::id(),
+        event.action,
+        structured_data(&event.details)
+    )
+}
+
+/// Sends events to the local syslog daemon over its datagram socket, usually `/dev/log`.
+#[cfg(unix)]
+pub struct SyslogLogger {
+    socket: std::os::unix::net::UnixDatagram,
+    facility: u8,
+    hostname: String,
+    app_name: String,
+}
+
+#[cfg(unix)]
+impl SyslogLogger {
+    pub fn connect(socket: &Path, app_name: &str, facility: u8) -> io::Result<Self> {
+        let datagram = std::os::unix::net::UnixDatagram::unbound()?;
+        datagram.connect(socket)?;
+        let hostname = fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+        Ok(SyslogLogger { socket: datagram, facility, hostname, app_name: app_name.to_string() })
+    }
+}
+
+#[cfg(unix)]
+impl ExternalLogger for SyslogLogger {
+    fn log_event(&self, event: &AuditEvent) {
+        if let Err(e) = self.try_log_event(event) {
+            eprintln!("[audit] failed to send to syslog: {}", e);
+        }
+    }
+
+    fn try_log_event(&self, event: &AuditEvent) -> io::Result<()> {
+        let message = format_rfc5424(event, self.facility, &self.hostname, &self.app_name);
+        self.socket.send(message.as_bytes()).map(|_| ())
+    }
+}
+
+/// Forwards events to `tracing` under the `zkscanner::audit` target, for services that already
+/// ship their traces somewhere.
+pub struct TracingLogger;
+
+impl ExternalLogger for TracingLogger {
+    fn log_event(&self, event: &AuditEvent) {
+        if severity(&event.action) <= 4 {
+            tracing::warn!(target: "zkscanner::audit", timestamp = event.timestamp, action = %event.action, "{}", event.details);
+        } else {
+            tracing::info!(target: "zkscanner::audit", timestamp = event.timestamp, action = %event.action, "{}", event.details);
+        }
+    }
+}
+
+/// The `[audit_log]` section of `config/default.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum LoggerConfig {
+    #[default]
+    None,
+    Jsonl {
+        path: PathBuf,
+        #[serde(default = "default_max_bytes")]
+        max_bytes: u64,
+        #[serde(default = "default_max_files")]
+        max_files: usize,
+    },
+    Syslog {
+        #[serde(default = "default_syslog_socket")]
+        socket: PathBuf,
+        #[serde(default = "default_app_name")]
+        app_name: String,
+        /// 13 is "log audit".
+        #[serde(default = "default_facility")]
+        facility: u8,
+    },
+    Tracing,
+    /// Hash-chained, tamper-evident log with signed checkpoints.
+    Chained {
+        path: PathBuf,
+        /// File holding the hex-encoded 32-byte Ed25519 seed that signs checkpoints.
+        signing_key: PathBuf,
+        #[serde(default = "default_checkpoint_every")]
+        checkpoint_every: u64,
+    },
+}
+
+fn default_checkpoint_every() -> u64 {
+    100
+}
+
+fn default_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+fn default_syslog_socket() -> PathBuf {
+    PathBuf::from("/dev/log")
+}
+
+fn default_app_name() -> String {
+    "zkaudit".to_string()
+}
+
+fn default_facility() -> u8 {
+    13
+}
+
+impl LoggerConfig {
+    /// Build the configured logger, or `None` when logging is off.
+    pub fn build(&self) -> io::Result<Option<BoxedLogger>> {
+        Ok(match self {
+            LoggerConfig::None => None,
+            LoggerConfig::Jsonl { path, max_bytes, max_files } => {
+                Some(Box::new(JsonLinesLogger::new(path, *max_bytes, *max_files)?))
+            }
+            #[cfg(unix)]
+            LoggerConfig::Syslog { socket, app_name, facility } => {
+                Some(Box::new(SyslogLogger::connect(socket, app_name, *facility)?))
+            }
+            #[cfg(not(unix))]
+            LoggerConfig::Syslog { .. } => {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "syslog logging needs a Unix socket"))
+            }
+            LoggerConfig::Tracing => Some(Box::new(TracingLogger)),
+            LoggerConfig::Chained { path, signing_key, checkpoint_every } => {
+                let key = ed25519_dalek::SigningKey::from_bytes(&read_hex_key(signing_key)?);
+                let logger = HashChainLogger::open(path, key, *checkpoint_every)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                Some(Box::new(logger))
+            }
+        })
+    }
+}
+
+impl LoggerConfig {
+    /// Make the files this backend names relative to `base` instead of the working directory.
+    fn resolve_paths(&mut self, base: &Path) {
+        match self {
+            LoggerConfig::None | LoggerConfig::Tracing => {}
+            LoggerConfig::Jsonl { path, .. } => *path = base.join(&*path),
+            LoggerConfig::Syslog { socket, .. } => *socket = base.join(&*socket),
+            LoggerConfig::Chained { path, signing_key, .. } => {
+                *path = base.join(&*path);
+                *signing_key = base.join(&*signing_key);
+            }
+        }
+    }
+}
+
+/// Read a file holding a hex-encoded 32-byte key.
+fn read_hex_key(path: &Path) -> io::Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    hex::decode_to_slice(fs::read_to_string(path)?.trim(), &mut key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+    Ok(key)
+}
+
+/// Scanner settings read from a TOML file such as `config/default.toml`. Other sections are
+/// ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScannerConfig {
+    #[serde(default)]
+    pub audit_log: LoggerConfig,
+    /// What audit events may reveal about decrypted notes.
+    #[serde(default)]
+    pub redaction: RedactionPolicy,
+    /// When set, events are handed to the backend on a background thread instead of inline.
+    #[serde(default)]
+    pub audit_queue: Option<QueueOptions>,
+}
+
+impl ScannerConfig {
+    /// Parse a config file. Relative paths in it are taken relative to the file's directory, so
+    /// the same file works wherever the scanner is run from.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&fs::read_to_string(path)?, base)
+    }
+
+    /// Parse config text whose relative paths are relative to `base`, reading the redaction hash
+    /// key from `hash_key_file` if one is set.
+    pub fn parse(text: &str, base: &Path) -> io::Result<Self> {
+        let mut config: ScannerConfig =
+            toml::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        config.audit_log.resolve_paths(base);
+        if let Some(key_file) = &config.redaction.hash_key_file {
+            let key_file = base.join(key_file);
+            config.redaction = config.redaction.clone().with_hash_key(read_hex_key(&key_file)?);
+            config.redaction.hash_key_file = Some(key_file);
+        }
+        Ok(config)
+    }
+
+    /// Build the configured backend, behind a [`ChannelLogger`] if `[audit_queue]` is set.
+    pub fn build_logger(&self) -> io::Result<Option<BoxedLogger>> {
+        let Some(backend) = self.audit_log.build()? else { return Ok(None) };
+        Ok(Some(match &self.audit_queue {
+            Some(options) => Box::new(ChannelLogger::spawn(LoggerSink(backend), options.clone())?),
+            None => backend,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: &str, metadata: &str) -> AuditEvent {
+        AuditEvent {
+            timestamp: 1_700_000_000,
+            action: action.to_string(),
+            details: EventDetails::new().with("metadata", metadata),
+        }
+    }
+
+    #[test]
+    fn test_jsonl_logger_rotates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let logger = JsonLinesLogger::new(&path, 150, 2).unwrap();
+        for i in 0..10 {
+            logger.log_event(&event("decryption_attempt", &format!("note{}", i)));
+        }
+        let current = fs::read_to_string(&path).unwrap();
+        let last: AuditEvent = serde_json::from_str(current.lines().last().unwrap()).unwrap();
+        assert_eq!(last.details.get("metadata"), Some("note9"));
+        assert!(dir.path().join("audit.jsonl.1").exists());
+        assert!(dir.path().join("audit.jsonl.2").exists());
+        assert!(!dir.path().join("audit.jsonl.3").exists());
+    }
+
+    #[test]
+    fn test_rfc5424_format_and_config() {
+        let line = format_rfc5424(&event("metadata_tampered", "note3"), 13, "host1", "zkaudit");
+        let expected = format!(
+            "<108>1 2023-11-14T22:13:20Z host1 zkaudit {} metadata_tampered [zkaudit@32473 metadata=\"note3\"]",
+            std::process::id()
+        );
+        assert_eq!(line, expected);
+        let quoted = format_rfc5424(&event("metadata_tampered", "a\"b]"), 13, "host1", "zkaudit");
+        assert!(quoted.ends_with(r#"[zkaudit@32473 metadata="a\"b\]"]"#));
+
+        let config: ScannerConfig = toml::from_str(
+            "[general]\n[audit_log]\nbackend = \"jsonl\"\npath = \"logs/audit.jsonl\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.audit_log,
+            LoggerConfig::Jsonl { path: "logs/audit.jsonl".into(), max_bytes: default_max_bytes(), max_files: 5 }
+        );
+        let config: ScannerConfig = toml::from_str(include_str!("../config/default.toml")).unwrap();
+        assert_eq!(config.audit_log, LoggerConfig::None);
+        assert_eq!(config.redaction.amount, crate::redact::FieldRedaction::Omit);
+        assert_eq!(config.redaction.memo, crate::redact::FieldRedaction::Omit);
+        assert_eq!(config.audit_queue, None);
+
+        let config: ScannerConfig = toml::from_str("[audit_queue]\nbatch_size = 8\nbackpressure = \"block\"\n").unwrap();
+        let queue = config.audit_queue.unwrap();
+        assert_eq!(queue.batch_size, 8);
+        assert_eq!(queue.backpressure, crate::channel_logger::Backpressure::Block);
+        assert_eq!(queue.capacity, QueueOptions::default().capacity);
+    }
+
+    #[test]
+    fn test_hash_key_file_correlates_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("redaction.hex");
+        fs::write(&key_path, format!("{}\n", hex::encode([3u8; 32]))).unwrap();
+        let config_path = dir.path().join("scanner.toml");
+        // Relative to the config file, not to the directory the test runs in.
+        fs::write(&config_path, "[redaction]\namount = \"hash\"\nhash_key_file = \"redaction.hex\"\n").unwrap();
+
+        let payload = crate::NotePayload { amount: 42, ..crate::NotePayload::default() };
+        let amount = |policy: &RedactionPolicy| policy.payload(EventDetails::new(), &payload).get("amount").map(str::to_string);
+        let first = ScannerConfig::load(&config_path).unwrap().redaction;
+        let second = ScannerConfig::load(&config_path).unwrap().redaction;
+        assert_eq!(amount(&first), amount(&second));
+        assert_eq!(amount(&first), amount(&second.clone().with_hash_key([3u8; 32])));
+        // Without a key file every load hashes under a fresh key.
+        let unkeyed: RedactionPolicy = toml::from_str("amount = \"hash\"").unwrap();
+        assert_ne!(amount(&unkeyed), amount(&first));
+    }
+
+    #[test]
+    fn test_relative_paths_follow_the_config_file() {
+        let text = "[audit_log]\nbackend = \"chained\"\npath = \"logs/audit.chain.jsonl\"\nsigning_key = \"/etc/zkaudit/signing.hex\"\n";
+        let config = ScannerConfig::parse(text, Path::new("/srv/zkaudit/config")).unwrap();
+        assert_eq!(
+            config.audit_log,
+            LoggerConfig::Chained {
+                path: PathBuf::from("/srv/zkaudit/config/logs/audit.chain.jsonl"),
+                signing_key: PathBuf::from("/etc/zkaudit/signing.hex"),
+                checkpoint_every: 100,
+            }
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use crate::error::NoteError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A note record as delivered by an indexer, tagged with the label of the view key it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub id: u64,
+    pub view_key: String,
+    pub data: String,
+}
+
+/// Select the notes tagged with `view_key`, preserving their order.
+///
+/// Fails on an empty view key, and on duplicate note ids anywhere in `notes` since a duplicate
+/// means the indexer delivered an inconsistent set.
+pub fn scan_notes<'a>(notes: &'a [Note], view_key: &str) -> Result<Vec<&'a Note>, NoteError> {
+    if view_key.trim().is_empty() {
+        return Err(NoteError::InvalidViewKey);
+    }
+    let mut seen = HashSet::with_capacity(notes.len());
+    if !notes.iter().all(|note| seen.insert(note.id)) {
+        return Err(NoteError::DuplicateNoteId);
+    }
+    Ok(notes.iter().filter(|note| note.view_key == view_key).collect())
+}
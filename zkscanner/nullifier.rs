@@ -0,0 +1,190 @@
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::decrypt_notes::{DecryptedNote, NotePayload};
+
+pub type Hash = [u8; 32];
+
+/// Secret from which note nullifiers are derived, the `secret` input of `main_nullifier`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NullifierKey(pub Hash);
+
+impl fmt::Debug for NullifierKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NullifierKey(..)")
+    }
+}
+
+// The hashes below must match guest/merkle.rs and guest/note.rs.
+
+/// Map arbitrary bytes into the field: SHA-256 with the top byte cleared.
+pub(crate) fn field_hash(data: &[u8]) -> Hash {
+    let mut out: Hash = Sha256::digest(data).into();
+    out[0] = 0;
+    out
+}
+
+/// Circom-compatible Poseidon over BN254 of two big-endian field elements.
+fn poseidon_hash2(a: Hash, b: Hash) -> Hash {
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).expect("two inputs are supported");
+    poseidon.hash_bytes_be(&[&a, &b]).expect("inputs are field elements")
+}
+
+/// Note commitment, as computed by the guests. `None` when the payload carries no blinding, or
+/// one that is not a field element (its top byte must be zero).
+pub fn note_commitment(payload: &NotePayload) -> Option<Hash> {
+    let blinding = payload.blinding.filter(|b| b[0] == 0)?;
+    let mut amount_padded = [0u8; 32];
+    amount_padded[24..].copy_from_slice(&payload.amount.to_be_bytes());
+    let recipient_hash = field_hash(payload.recipient.as_bytes());
+    let memo_hash = payload.memo.as_deref().map_or([0u8; 32], |m| field_hash(m.as_bytes()));
+    let inner = poseidon_hash2(amount_padded, recipient_hash);
+    let inner = poseidon_hash2(inner, memo_hash);
+    Some(poseidon_hash2(inner, blinding))
+}
+
+/// Nullifier = Poseidon(note_commitment, secret), exactly as `main_nullifier` proves it.
+pub fn nullifier(key: &NullifierKey, commitment: Hash) -> Hash {
+    poseidon_hash2(commitment, key.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpendStatus {
+    Unspent,
+    Spent,
+    /// The note carries no blinding, so its nullifier cannot be derived.
+    Unknown,
+}
+
+/// A scanned note with its derived nullifier and spend status.
+#[derive(Debug, Clone)]
+pub struct TrackedNote<'a> {
+    pub note: &'a DecryptedNote,
+    pub commitment: Option<Hash>,
+    pub nullifier: Option<Hash>,
+    pub status: SpendStatus,
+}
+
+/// Something in the nullifier set that should never happen for an honest wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpendAnomaly {
+    /// One of our nullifiers was published more than once.
+    DoubleSpend { #[serde(with = "hex")] nullifier: Hash, count: usize },
+    /// Several scanned notes share a commitment, so only one of them can ever be spent.
+    DuplicateNote { #[serde(with = "hex")] commitment: Hash, count: usize },
+}
+
+pub struct SpendReport<'a> {
+    pub notes: Vec<TrackedNote<'a>>,
+    /// Sum of unspent notes per asset ID, counting notes that share a commitment once since only
+    /// one of them can be spent. Notes with unknown status are not counted. The asset ID is not
+    /// part of the note commitment, so it is only as trustworthy as the sender: a note's label
+    /// can claim any asset without changing what the note proves.
+    pub balances: BTreeMap<String, u64>,
+    pub anomalies: Vec<SpendAnomaly>,
+}
+
+/// Derive the nullifier of every scanned note and check it against the nullifiers published
+/// on chain, which may contain duplicates.
+pub fn track_spends<'a>(notes: &'a [DecryptedNote], key: &NullifierKey, onchain: &[Hash]) -> SpendReport<'a> {
+    let mut published: HashMap<Hash, usize> = HashMap::new();
+    for n in onchain {
+        *published.entry(*n).or_default() += 1;
+    }
+    let mut commitments: BTreeMap<Hash, usize> = BTreeMap::new();
+    let mut double_spent: BTreeMap<Hash, usize> = BTreeMap::new();
+    let mut balances: BTreeMap<String, u64> = BTreeMap::new();
+    let tracked = notes
+        .iter()
+        .map(|note| {
+            let commitment = note_commitment(&note.parsed);
+            let nullifier = commitment.map(|c| nullifier(key, c));
+            let status = match nullifier.map(|n| published.get(&n).copied().unwrap_or(0)) {
+                None => SpendStatus::Unknown,
+                Some(0) => SpendStatus::Unspent,
+                Some(count) => {
+                    if count > 1 {
+                        double_spent.insert(nullifier.unwrap(), count);
+                    }
+                    SpendStatus::Spent
+                }
+            };
+            let first = commitment.is_some_and(|c| {
+                let count = commitments.entry(c).or_default();
+                *count += 1;
+                *count == 1
+            });
+            if status == SpendStatus::Unspent && first {
+                let balance = balances.entry(note.parsed.asset_id.clone()).or_default();
+                *balance = balance.saturating_add(note.parsed.amount);
+            }
+            TrackedNote { note, commitment, nullifier, status }
+        })
+        .collect();
+    let anomalies = double_spent
+        .into_iter()
+        .map(|(nullifier, count)| SpendAnomaly::DoubleSpend { nullifier, count })
+        .chain(
+            commitments
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(commitment, count)| SpendAnomaly::DuplicateNote { commitment, count }),
+        )
+        .collect();
+    SpendReport { notes: tracked, balances, anomalies }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::NATIVE_ASSET;
+    use crate::keys::SpendingKey;
+
+    fn note(amount: u64, blinding: Option<u8>) -> DecryptedNote {
+        let parsed = NotePayload {
+            amount,
+            recipient: "alice".to_string(),
+            memo: None,
+            blinding: blinding.map(|b| {
+                let mut out = [0u8; 32];
+                out[31] = b;
+                out
+            }),
+            ..NotePayload::default()
+        };
+        DecryptedNote {
+            note_id: format!("{:032x}", amount),
+            view_key: String::new(),
+            plaintext: vec![],
+            metadata: format!("note{}", amount),
+            parsed,
+            expires_at: None,
+            expired: false,
+            audit_trail: vec![],
+        }
+    }
+
+    #[test]
+    fn test_spent_notes_balance_and_anomalies() {
+        let key = SpendingKey([1u8; 32]).nullifier_key();
+        let mut usdc = note(7, Some(4));
+        usdc.parsed.asset_id = "usdc".to_string();
+        let notes = vec![note(10, Some(1)), note(20, Some(2)), note(30, Some(3)), note(40, None), note(30, Some(3)), usdc];
+        let spent = nullifier(&key, note_commitment(&notes[0].parsed).unwrap());
+        let double = nullifier(&key, note_commitment(&notes[1].parsed).unwrap());
+        let foreign = nullifier(&SpendingKey([2u8; 32]).nullifier_key(), note_commitment(&notes[2].parsed).unwrap());
+
+        let report = track_spends(&notes, &key, &[spent, double, foreign, double]);
+        let statuses: Vec<SpendStatus> = report.notes.iter().map(|n| n.status).collect();
+        use SpendStatus::*;
+        assert_eq!(statuses, vec![Spent, Spent, Unspent, Unknown, Unspent, Unspent]);
+        assert_eq!(report.balances, BTreeMap::from([(NATIVE_ASSET.to_string(), 30), ("usdc".to_string(), 7)]));
+        assert_eq!(report.anomalies.len(), 2);
+        assert_eq!(report.anomalies[0], SpendAnomaly::DoubleSpend { nullifier: double, count: 2 });
+        assert!(matches!(report.anomalies[1], SpendAnomaly::DuplicateNote { count: 2, .. }));
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::decrypt_notes::NotePayload;
+
+/// Schema version written by [`encode_payload`].
+pub const CURRENT_VERSION: u8 = 2;
+
+/// Length of the envelope header: format, schema version and payload type, one byte each.
+const HEADER_LEN: usize = 3;
+
+/// How the body after the envelope header is serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    Json = 1,
+    Bincode = 2,
+}
+
+impl PayloadFormat {
+    fn from_byte(byte: u8) -> Result<Self, PayloadError> {
+        match byte {
+            1 => Ok(PayloadFormat::Json),
+            2 => Ok(PayloadFormat::Bincode),
+            other => Err(PayloadError::UnknownFormat(other)),
+        }
+    }
+}
+
+/// What kind of object the envelope carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadType {
+    Note = 1,
+}
+
+impl PayloadType {
+    fn from_byte(byte: u8) -> Result<Self, PayloadError> {
+        match byte {
+            1 => Ok(PayloadType::Note),
+            other => Err(PayloadError::UnknownType(other)),
+        }
+    }
+}
+
+/// Why a decrypted plaintext could not be decoded as a note payload.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PayloadError {
+    #[error("payload shorter than its 3-byte envelope header")]
+    Truncated,
+    #[error("unknown payload format {0}")]
+    UnknownFormat(u8),
+    #[error("unsupported payload schema version {version} (latest is {latest})")]
+    UnsupportedVersion { version: u8, latest: u8 },
+    #[error("unknown payload type {0}")]
+    UnknownType(u8),
+    #[error("malformed payload body: {0}")]
+    Malformed(String),
+}
+
+/// Version 1 schema, the plain amount, recipient and memo notes carried before the envelope.
+#[derive(Serialize, Deserialize)]
+struct NotePayloadV1 {
+    amount: u64,
+    recipient: String,
+    memo: Option<String>,
+}
+
+impl From<NotePayloadV1> for NotePayload {
+    fn from(v1: NotePayloadV1) -> Self {
+        NotePayload { amount: v1.amount, recipient: v1.recipient, memo: v1.memo, ..NotePayload::default() }
+    }
+}
+
+fn decode_body<T: for<'de> Deserialize<'de>>(format: PayloadFormat, body: &[u8]) -> Result<T, PayloadError> {
+    match format {
+        PayloadFormat::Json => serde_json::from_slice(body).map_err(|e| PayloadError::Malformed(e.to_string())),
+        PayloadFormat::Bincode => bincode::deserialize(body).map_err(|e| PayloadError::Malformed(e.to_string())),
+    }
+}
+
+/// Wrap a note payload in a versioned envelope at the current schema version.
+pub fn encode_payload(payload: &NotePayload, format: PayloadFormat) -> Result<Vec<u8>, PayloadError> {
+    let mut out = vec![format as u8, CURRENT_VERSION, PayloadType::Note as u8];
+    match format {
+        PayloadFormat::Json => serde_json::to_writer(&mut out, payload).map_err(|e| PayloadError::Malformed(e.to_string()))?,
+        PayloadFormat::Bincode => bincode::serialize_into(&mut out, payload).map_err(|e| PayloadError::Malformed(e.to_string()))?,
+    }
+    Ok(out)
+}
+
+/// Decode an envelope written by [`encode_payload`] at any known schema version, migrating older
+/// versions to the current [`NotePayload`]. The header says exactly how to read the body, so
+/// nothing is guessed.
+pub fn decode_payload(bytes: &[u8]) -> Result<NotePayload, PayloadError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(PayloadError::Truncated);
+    }
+    let format = PayloadFormat::from_byte(bytes[0])?;
+    let version = bytes[1];
+    // Notes are the only payload type so far.
+    PayloadType::from_byte(bytes[2])?;
+    let body = &bytes[HEADER_LEN..];
+    match version {
+        1 => decode_body::<NotePayloadV1>(format, body).map(NotePayload::from),
+        CURRENT_VERSION => decode_body(format, body),
+        version => Err(PayloadError::UnsupportedVersion { version, latest: CURRENT_VERSION }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::NATIVE_ASSET;
+    use crate::keys::SpendingKey;
+
+    #[test]
+    fn test_envelope_round_trip_and_migration() {
+        let payload = NotePayload {
+            amount: 7,
+            recipient: "carol".to_string(),
+            memo: Some("{}".to_string()),
+            blinding: Some([9u8; 32]),
+            asset_id: "usdc".to_string(),
+            reference: Some("INV-3".to_string()),
+            memo_fields: [("order_id".to_string(), "77".to_string())].into(),
+            ..NotePayload::default()
+        };
+        for format in [PayloadFormat::Json, PayloadFormat::Bincode] {
+            assert_eq!(decode_payload(&encode_payload(&payload, format).unwrap()).unwrap(), payload);
+        }
+
+        let v1 = NotePayloadV1 { amount: 7, recipient: "carol".to_string(), memo: None };
+        let mut bytes = vec![PayloadFormat::Bincode as u8, 1, PayloadType::Note as u8];
+        bytes.extend(bincode::serialize(&v1).unwrap());
+        let migrated = decode_payload(&bytes).unwrap();
+        assert_eq!(migrated.amount, 7);
+        assert_eq!(migrated.blinding, None);
+        assert_eq!(migrated.asset_id, NATIVE_ASSET);
+
+        let payload = NotePayload { sender: Some(SpendingKey([1u8; 32]).epoch_address(2, "carol")), ..payload };
+        for format in [PayloadFormat::Json, PayloadFormat::Bincode] {
+            assert_eq!(decode_payload(&encode_payload(&payload, format).unwrap()).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_unknown_envelopes_are_typed_errors() {
+        let payload = NotePayload { amount: 1, recipient: "dan".to_string(), memo: None, ..NotePayload::default() };
+        let mut bytes = encode_payload(&payload, PayloadFormat::Json).unwrap();
+        bytes[1] = 9;
+        assert_eq!(decode_payload(&bytes), Err(PayloadError::UnsupportedVersion { version: 9, latest: CURRENT_VERSION }));
+        bytes[1] = CURRENT_VERSION;
+        bytes[2] = 4;
+        assert_eq!(decode_payload(&bytes), Err(PayloadError::UnknownType(4)));
+        // A bare JSON document, as written before envelopes, is no longer guessed at.
+        assert_eq!(decode_payload(b"{\"amount\":1}"), Err(PayloadError::UnknownFormat(b'{')));
+        assert_eq!(decode_payload(&[1, 2]), Err(PayloadError::Truncated));
+    }
+}
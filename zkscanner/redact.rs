@@ -0,0 +1,221 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use crate::decrypt_notes::NotePayload;
+
+const FIELD_HASH_DOMAIN: &[u8] = b"CipherPay_audit_field";
+
+/// Structured key/value details of an audit event, in key order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EventDetails(pub BTreeMap<String, String>);
+
+impl EventDetails {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, key: &str, value: impl ToString) -> Self {
+        self.0.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// `key=value` pairs separated by spaces.
+impl fmt::Display for EventDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// How one field is written into audit event details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldRedaction {
+    /// Leave the field out.
+    Omit,
+    /// A keyed hash of the value, so equal values can be correlated without being revealed.
+    Hash,
+    /// The first `n` characters, followed by `…` when anything was cut.
+    Truncate(usize),
+    /// The value as is.
+    Plain,
+}
+
+impl FieldRedaction {
+    fn apply(self, policy: &RedactionPolicy, field: &str, value: &str) -> Option<String> {
+        match self {
+            FieldRedaction::Omit => None,
+            FieldRedaction::Hash => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&policy.hash_key).expect("HMAC takes keys of any length");
+                mac.update(FIELD_HASH_DOMAIN);
+                mac.update(&(field.len() as u64).to_le_bytes());
+                mac.update(field.as_bytes());
+                mac.update(value.as_bytes());
+                Some(hex::encode(&mac.finalize().into_bytes()[..8]))
+            }
+            FieldRedaction::Truncate(n) => match value.char_indices().nth(n) {
+                Some((end, _)) => Some(format!("{}…", &value[..end])),
+                None => Some(value.to_string()),
+            },
+            FieldRedaction::Plain => Some(value.to_string()),
+        }
+    }
+}
+
+/// Which decrypted fields reach audit events, and in what form.
+///
+/// Audit events end up in files, syslog and tracing backends that are usually far less
+/// protected than the wallet, so by default no decrypted content is logged: payload fields are
+/// omitted and only the note's public metadata is kept. Hashes are keyed with a random key per
+/// policy, so they only correlate events from the same run; set `hash_key_file` or use
+/// [`with_hash_key`](Self::with_hash_key) to correlate across runs. Hashes are truncated
+/// HMAC-SHA256 under that key.
+///
+/// Equality compares the configured redactions, not the hash key, so two policies read from the
+/// same config are equal even though each drew its own random key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionPolicy {
+    /// Note metadata, which travels unencrypted with the note.
+    pub metadata: FieldRedaction,
+    pub amount: FieldRedaction,
+    pub asset_id: FieldRedaction,
+    pub recipient: FieldRedaction,
+    pub memo: FieldRedaction,
+    pub reference: FieldRedaction,
+    /// File holding a hex-encoded 32-byte hash key, read by
+    /// [`ScannerConfig::load`](crate::ScannerConfig::load). Anyone holding the key can test
+    /// guesses against hashed fields, so keep it as private as the wallet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_key_file: Option<PathBuf>,
+    #[serde(skip, default = "random_hash_key")]
+    hash_key: [u8; 32],
+}
+
+fn random_hash_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        RedactionPolicy {
+            metadata: FieldRedaction::Plain,
+            amount: FieldRedaction::Omit,
+            asset_id: FieldRedaction::Omit,
+            recipient: FieldRedaction::Omit,
+            memo: FieldRedaction::Omit,
+            reference: FieldRedaction::Omit,
+            hash_key_file: None,
+            hash_key: random_hash_key(),
+        }
+    }
+}
+
+impl PartialEq for RedactionPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata
+            && self.amount == other.amount
+            && self.asset_id == other.asset_id
+            && self.recipient == other.recipient
+            && self.memo == other.memo
+            && self.reference == other.reference
+            && self.hash_key_file == other.hash_key_file
+    }
+}
+
+impl Eq for RedactionPolicy {}
+
+impl RedactionPolicy {
+    pub fn with_hash_key(mut self, key: [u8; 32]) -> Self {
+        self.hash_key = key;
+        self
+    }
+
+    /// Add the note's metadata to `details`, as the policy allows.
+    pub(crate) fn metadata(&self, details: EventDetails, metadata: &str) -> EventDetails {
+        self.field(details, "metadata", self.metadata, metadata)
+    }
+
+    /// Add the decrypted payload fields to `details`, as the policy allows.
+    pub(crate) fn payload(&self, details: EventDetails, payload: &NotePayload) -> EventDetails {
+        let details = self.field(details, "amount", self.amount, &payload.amount.to_string());
+        let details = self.field(details, "asset_id", self.asset_id, &payload.asset_id);
+        let details = self.field(details, "recipient", self.recipient, &payload.recipient);
+        let details = match &payload.memo {
+            Some(memo) => self.field(details, "memo", self.memo, memo),
+            None => details,
+        };
+        match &payload.reference {
+            Some(reference) => self.field(details, "reference", self.reference, reference),
+            None => details,
+        }
+    }
+
+    fn field(&self, details: EventDetails, name: &str, redaction: FieldRedaction, value: &str) -> EventDetails {
+        match redaction.apply(self, name, value) {
+            Some(value) => details.with(name, value),
+            None => details,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_redactions() {
+        let payload = NotePayload {
+            amount: 1500,
+            recipient: "bob".to_string(),
+            memo: Some("rent for march".to_string()),
+            reference: Some("INV-1042".to_string()),
+            ..NotePayload::default()
+        };
+        let policy = RedactionPolicy {
+            amount: FieldRedaction::Hash,
+            recipient: FieldRedaction::Plain,
+            memo: FieldRedaction::Truncate(4),
+            ..RedactionPolicy::default()
+        }
+        .with_hash_key([1u8; 32]);
+        let details = policy.payload(EventDetails::new(), &payload);
+        assert_eq!(details.get("recipient"), Some("bob"));
+        assert_eq!(details.get("memo"), Some("rent…"));
+        assert_eq!(details.get("reference"), None);
+        let hashed = details.get("amount").unwrap();
+        assert_eq!(hashed.len(), 16);
+        assert_ne!(hashed, "1500");
+        assert_eq!(policy.payload(EventDetails::new(), &payload).get("amount"), Some(hashed));
+        let rekeyed = policy.clone().with_hash_key([2u8; 32]);
+        assert_ne!(rekeyed.payload(EventDetails::new(), &payload).get("amount"), Some(hashed));
+        assert_eq!(rekeyed, policy);
+        assert_eq!(RedactionPolicy::default(), RedactionPolicy::default());
+
+        let config: RedactionPolicy = toml::from_str("memo = { truncate = 8 }\namount = \"hash\"\n").unwrap();
+        assert_eq!(config.memo, FieldRedaction::Truncate(8));
+        assert_eq!(config.amount, FieldRedaction::Hash);
+        assert_eq!(config.recipient, FieldRedaction::Omit);
+    }
+}
@@ -0,0 +1,311 @@
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use hkdf::Hkdf;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use crate::decrypt_notes::{AuditEvent, DecryptedNote, NotePayload};
+use crate::error::StoreError;
+use crate::nullifier::{SpendReport, SpendStatus};
+
+const NOTES_TREE: &str = "notes";
+const SALT_KEY: &str = "salt";
+const CHECK_KEY: &str = "password_check";
+const CHECK_PLAINTEXT: &[u8] = b"CipherPay_note_store";
+const RECORD_KEY_INFO: &[u8] = b"CipherPay_store_record_key";
+const ID_KEY_INFO: &[u8] = b"CipherPay_store_id_key";
+/// Leads every encoded [`StoredNote`], so the record layout can change without misreading
+/// older records.
+const RECORD_VERSION: u8 = 1;
+
+/// A decrypted note as kept in the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredNote {
+    /// The note's [`DecryptedNote::note_id`].
+    pub id: String,
+    /// Fingerprint of the view key that decrypted the note.
+    pub view_key: String,
+    pub plaintext: Vec<u8>,
+    pub metadata: String,
+    pub payload: NotePayload,
+    pub expires_at: Option<i64>,
+    pub expired: bool,
+    pub spent: bool,
+    /// Unix timestamp of the scan that found the note.
+    pub scanned_at: i64,
+    pub audit_trail: Vec<AuditEvent>,
+}
+
+/// Criteria for [`NoteStore::query`]. Unset fields match every note.
+#[derive(Debug, Clone, Default)]
+pub struct NoteQuery {
+    pub recipient: Option<String>,
+    pub asset_id: Option<String>,
+    pub reference: Option<String>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    pub scanned_since: Option<i64>,
+    pub scanned_until: Option<i64>,
+    /// Bounds on the sender's `created_at`. That timestamp is only as trustworthy as the sender,
+    /// and notes without one never match a bound.
+    pub created_since: Option<i64>,
+    pub created_until: Option<i64>,
+    pub memo_contains: Option<String>,
+    pub spent: Option<bool>,
+}
+
+impl NoteQuery {
+    pub fn matches(&self, note: &StoredNote) -> bool {
+        self.recipient.as_ref().is_none_or(|r| &note.payload.recipient == r)
+            && self.asset_id.as_ref().is_none_or(|a| &note.payload.asset_id == a)
+            && self.reference.as_ref().is_none_or(|r| note.payload.reference.as_ref() == Some(r))
+            && self.min_amount.is_none_or(|min| note.payload.amount >= min)
+            && self.max_amount.is_none_or(|max| note.payload.amount <= max)
+            && self.scanned_since.is_none_or(|t| note.scanned_at >= t)
+            && self.scanned_until.is_none_or(|t| note.scanned_at <= t)
+            && self.created_since.is_none_or(|t| note.payload.created_at.is_some_and(|c| c >= t))
+            && self.created_until.is_none_or(|t| note.payload.created_at.is_some_and(|c| c <= t))
+            && self.memo_contains.as_ref().is_none_or(|text| {
+                note.payload.memo.as_deref().is_some_and(|memo| memo.contains(text.as_str()))
+            })
+            && self.spent.is_none_or(|spent| note.spent == spent)
+    }
+}
+
+/// Local database of scanned notes, encrypted at rest under a key derived from the wallet
+/// password with Argon2.
+///
+/// Every record is sealed with AES-256-GCM under its own random nonce. Records are keyed by a
+/// keyed hash of the note id, so the database reveals how many notes it holds but not which
+/// ones.
+/// Since contents are encrypted, queries decrypt and filter every record.
+pub struct NoteStore {
+    db: sled::Db,
+    notes: sled::Tree,
+    cipher: Aes256Gcm,
+    id_key: [u8; 32],
+}
+
+impl NoteStore {
+    /// Open or create the store at `path`. Fails with [`StoreError::WrongPassword`] when the
+    /// password does not match the one the store was created with.
+    pub fn open(path: &Path, password: &str) -> Result<Self, StoreError> {
+        let db = sled::open(path)?;
+        let salt = match db.get(SALT_KEY)? {
+            Some(salt) => salt.to_vec(),
+            None => {
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                db.insert(SALT_KEY, &salt[..])?;
+                salt.to_vec()
+            }
+        };
+        let mut master = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(password.as_bytes(), &salt, &mut master)
+            .map_err(|e| StoreError::Corrupt(e.to_string()))?;
+        let hk = Hkdf::<Sha256>::new(Some(&salt), &master);
+        let mut record_key = [0u8; 32];
+        let mut id_key = [0u8; 32];
+        hk.expand(RECORD_KEY_INFO, &mut record_key).expect("32 bytes is a valid HKDF output length");
+        hk.expand(ID_KEY_INFO, &mut id_key).expect("32 bytes is a valid HKDF output length");
+
+        let store = NoteStore {
+            notes: db.open_tree(NOTES_TREE)?,
+            db,
+            cipher: Aes256Gcm::new(Key::from_slice(&record_key)),
+            id_key,
+        };
+        match store.db.get(CHECK_KEY)? {
+            Some(sealed) => {
+                if store.open_record(CHECK_KEY.as_bytes(), &sealed).ok().as_deref() != Some(CHECK_PLAINTEXT) {
+                    return Err(StoreError::WrongPassword);
+                }
+            }
+            None => {
+                let sealed = store.seal_record(CHECK_KEY.as_bytes(), CHECK_PLAINTEXT)?;
+                store.db.insert(CHECK_KEY, sealed)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Store a scanned note, replacing an earlier copy of the same note but keeping its spent
+    /// status. Returns the note's id.
+    pub fn put(&self, note: &DecryptedNote, scanned_at: i64) -> Result<String, StoreError> {
+        let id = note.note_id.clone();
+        let spent = self.get(&id)?.is_some_and(|existing| existing.spent);
+        self.write(&StoredNote {
+            id: id.clone(),
+            view_key: note.view_key.clone(),
+            plaintext: note.plaintext.clone(),
+            metadata: note.metadata.clone(),
+            payload: note.parsed.clone(),
+            expires_at: note.expires_at,
+            expired: note.expired,
+            spent,
+            scanned_at,
+            audit_trail: note.audit_trail.clone(),
+        })?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<StoredNote>, StoreError> {
+        let key = self.record_key(id);
+        self.notes
+            .get(&key)?
+            .map(|sealed| self.decode(key.as_bytes(), &sealed))
+            .transpose()
+    }
+
+    pub fn set_spent(&self, id: &str, spent: bool) -> Result<(), StoreError> {
+        let mut note = self.get(id)?.ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        note.spent = spent;
+        self.write(&note)
+    }
+
+    /// Mark every stored note the report found spent. Returns how many notes changed.
+    pub fn apply_spends(&self, report: &SpendReport<'_>) -> Result<usize, StoreError> {
+        let mut changed = 0;
+        for tracked in report.notes.iter().filter(|t| t.status == SpendStatus::Spent) {
+            if let Some(mut stored) = self.get(&tracked.note.note_id)? {
+                if !stored.spent {
+                    stored.spent = true;
+                    self.write(&stored)?;
+                    changed += 1;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Every stored note matching `query`, in id order.
+    pub fn query(&self, query: &NoteQuery) -> Result<Vec<StoredNote>, StoreError> {
+        let mut found = Vec::new();
+        for entry in self.notes.iter() {
+            let (id, sealed) = entry?;
+            let note = self.decode(&id, &sealed)?;
+            if query.matches(&note) {
+                found.push(note);
+            }
+        }
+        Ok(found)
+    }
+
+    pub fn flush(&self) -> Result<(), StoreError> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Database key for the note with id `id`.
+    fn record_key(&self, id: &str) -> String {
+        let digest = Sha256::new().chain_update(self.id_key).chain_update(id.as_bytes()).finalize();
+        hex::encode(&digest[..16])
+    }
+
+    fn write(&self, note: &StoredNote) -> Result<(), StoreError> {
+        let mut encoded = vec![RECORD_VERSION];
+        bincode::serialize_into(&mut encoded, note).map_err(|e| StoreError::Corrupt(e.to_string()))?;
+        let key = self.record_key(&note.id);
+        let sealed = self.seal_record(key.as_bytes(), &encoded)?;
+        self.notes.insert(key.as_bytes(), sealed)?;
+        Ok(())
+    }
+
+    fn decode(&self, key: &[u8], sealed: &[u8]) -> Result<StoredNote, StoreError> {
+        let encoded = self.open_record(key, sealed)?;
+        match encoded.split_first() {
+            Some((&RECORD_VERSION, record)) => {
+                bincode::deserialize(record).map_err(|e| StoreError::Corrupt(e.to_string()))
+            }
+            Some((version, _)) => Err(StoreError::Corrupt(format!("unsupported record version {}", version))),
+            None => Err(StoreError::Corrupt("empty record".to_string())),
+        }
+    }
+
+    /// Encrypt a record bound to its key, as `nonce || ciphertext`.
+    fn seal_record(&self, id: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: id })
+            .map_err(|_| StoreError::Corrupt("encryption failed".to_string()))?;
+        Ok([&nonce[..], &ciphertext].concat())
+    }
+
+    fn open_record(&self, id: &[u8], sealed: &[u8]) -> Result<Vec<u8>, StoreError> {
+        if sealed.len() < 12 {
+            return Err(StoreError::Corrupt("record too short".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: id })
+            .map_err(|_| StoreError::Corrupt(format!("record {} failed to decrypt", String::from_utf8_lossy(id))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decrypted(amount: u64, recipient: &str, memo: Option<&str>) -> DecryptedNote {
+        let parsed = NotePayload {
+            amount,
+            recipient: recipient.to_string(),
+            memo: memo.map(str::to_string),
+            created_at: (amount < 300).then_some(amount as i64),
+            ..NotePayload::default()
+        };
+        DecryptedNote {
+            note_id: format!("{:032x}", amount),
+            view_key: "key1".to_string(),
+            plaintext: serde_json::to_vec(&parsed).unwrap(),
+            metadata: format!("note{}", amount),
+            parsed,
+            expires_at: Some(1000 + amount as i64),
+            expired: false,
+            audit_trail: vec![],
+        }
+    }
+
+    #[test]
+    fn test_store_round_trip_and_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = {
+            let store = NoteStore::open(dir.path(), "hunter2").unwrap();
+            let id = store.put(&decrypted(100, "alice", Some("rent march")), 10).unwrap();
+            store.put(&decrypted(5, "alice", None), 20).unwrap();
+            store.put(&decrypted(300, "bob", Some("rent april")), 30).unwrap();
+            store.set_spent(&id, true).unwrap();
+            store.flush().unwrap();
+            id
+        };
+
+        assert_eq!(NoteStore::open(dir.path(), "wrong").err(), Some(StoreError::WrongPassword));
+        let store = NoteStore::open(dir.path(), "hunter2").unwrap();
+        // Storing a note again keeps its spent status.
+        assert_eq!(store.put(&decrypted(100, "alice", Some("rent march")), 40).unwrap(), id);
+        let stored = store.get(&id).unwrap().unwrap();
+        assert!(stored.spent);
+        assert_eq!(id, decrypted(100, "alice", None).note_id);
+        assert_eq!((stored.view_key.as_str(), stored.expires_at), ("key1", Some(1100)));
+        // The database is keyed by a keyed hash of the note id, not the id itself.
+        assert!(store.notes.get(&id).unwrap().is_none());
+
+        let amounts = |query: NoteQuery| {
+            let mut amounts: Vec<u64> = store.query(&query).unwrap().iter().map(|n| n.payload.amount).collect();
+            amounts.sort_unstable();
+            amounts
+        };
+        assert_eq!(amounts(NoteQuery { recipient: Some("alice".into()), ..Default::default() }), vec![5, 100]);
+        assert_eq!(amounts(NoteQuery { min_amount: Some(50), max_amount: Some(200), ..Default::default() }), vec![100]);
+        assert_eq!(amounts(NoteQuery { memo_contains: Some("rent".into()), ..Default::default() }), vec![100, 300]);
+        assert_eq!(amounts(NoteQuery { scanned_since: Some(15), scanned_until: Some(35), ..Default::default() }), vec![5, 300]);
+        assert_eq!(amounts(NoteQuery { spent: Some(false), ..Default::default() }), vec![5, 300]);
+        assert_eq!(amounts(NoteQuery { created_since: Some(50), ..Default::default() }), vec![100]);
+        assert_eq!(amounts(NoteQuery { created_until: Some(100), ..Default::default() }), vec![5, 100]);
+    }
+}
@@ -0,0 +1,411 @@
+use futures::executor::block_on_stream;
+use futures::Stream;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::mpsc as async_mpsc;
+use crate::decrypt_notes::{decrypt_note, scan_keys, DecryptedNote, EncryptedNote, ExternalLogger, ScanKey};
+use crate::error::NoteError;
+use crate::keys::IncomingViewKey;
+use crate::redact::RedactionPolicy;
+
+/// Logger shared by the scanner's worker threads.
+pub type SharedLogger = Arc<dyn ExternalLogger + Send + Sync>;
+
+/// Tuning for [`NoteScanner`].
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Number of worker threads. Defaults to the number of available cores.
+    pub workers: usize,
+    /// Yield notes in input order instead of as soon as they are decrypted.
+    pub ordered: bool,
+    /// Only scan notes whose metadata contains this substring.
+    pub filter: Option<String>,
+    /// Current Unix timestamp for expiration checks.
+    pub now: i64,
+    /// Notes buffered between the input and the workers, and between the workers and the
+    /// output. Bounds memory when the input is much faster than scanning.
+    pub buffer: usize,
+    /// What audit events may reveal about decrypted notes.
+    pub redaction: RedactionPolicy,
+    /// How many of the most recently yielded note ids are remembered to skip repeats. Bounds
+    /// the memory of an endless stream; 0 turns deduplication off.
+    pub dedupe_window: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            ordered: false,
+            filter: None,
+            now: chrono::Utc::now().timestamp(),
+            buffer: 1024,
+            redaction: RedactionPolicy::default(),
+            dedupe_window: 65_536,
+        }
+    }
+}
+
+/// Stops a running scan. Cloning shares the token.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Scans notes as they arrive, from an iterator or an async stream, on a pool of worker threads.
+///
+/// Every note is tried against every view key, exactly as in
+/// [`scan_wallet_notes_with_logger`](crate::scan_wallet_notes_with_logger). A note addressed to
+/// us that is malformed, tampered with or cannot be parsed is yielded as an error item and the
+/// scan goes on. A note that repeats one of the last
+/// [`dedupe_window`](ScanOptions::dedupe_window) notes yielded is left out.
+pub struct NoteScanner {
+    keys: Arc<Vec<ScanKey>>,
+    options: ScanOptions,
+    logger: Option<SharedLogger>,
+}
+
+impl NoteScanner {
+    pub fn new(view_keys: &[IncomingViewKey], options: ScanOptions) -> Self {
+        NoteScanner { keys: Arc::new(scan_keys(view_keys)), options, logger: None }
+    }
+
+    pub fn with_logger(mut self, logger: SharedLogger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Scan notes from an iterator. The iterator is drained on a background thread.
+    pub fn scan_iter<I>(&self, notes: I, cancel: &CancelToken) -> ScanResults
+    where
+        I: IntoIterator<Item = EncryptedNote>,
+        I::IntoIter: Send + 'static,
+    {
+        ScanResults(self.spawn(notes.into_iter(), cancel))
+    }
+
+    /// Scan notes from an async stream, e.g. an indexer subscription. When called inside a tokio
+    /// runtime the stream is polled within it, so runtime-backed streams work as input.
+    pub fn scan_stream<S>(&self, notes: S, cancel: &CancelToken) -> ScanStream
+    where
+        S: Stream<Item = EncryptedNote> + Send + Unpin + 'static,
+    {
+        let runtime = tokio::runtime::Handle::try_current().ok();
+        let notes = std::iter::from_fn({
+            let mut notes = block_on_stream(notes);
+            move || {
+                let _guard = runtime.as_ref().map(|handle| handle.enter());
+                notes.next()
+            }
+        });
+        ScanStream(self.spawn(notes, cancel))
+    }
+
+    fn spawn<I>(&self, notes: I, cancel: &CancelToken) -> Output
+    where
+        I: Iterator<Item = EncryptedNote> + Send + 'static,
+    {
+        let buffer = self.options.buffer.max(1);
+        let (job_tx, job_rx) = mpsc::sync_channel::<(u64, EncryptedNote)>(buffer);
+        let (result_tx, result_rx) = async_mpsc::channel(buffer);
+        // Stopped when the caller cancels or when the output is dropped.
+        let stop = Arc::new(AtomicBool::new(false));
+        let cancel = cancel.clone();
+        let stopped = {
+            let stop = stop.clone();
+            move || stop.load(Ordering::Relaxed) || cancel.is_cancelled()
+        };
+
+        thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                for (seq, note) in (0u64..).zip(notes) {
+                    if stopped() || job_tx.send((seq, note)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..self.options.workers.max(1) {
+            let job_rx = job_rx.clone();
+            let result_tx = result_tx.clone();
+            let stopped = stopped.clone();
+            let keys = self.keys.clone();
+            let filter = self.options.filter.clone();
+            let policy = self.options.redaction.clone();
+            let now = self.options.now;
+            let logger = self.logger.clone();
+            thread::spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((seq, note)) = job else { break };
+                if stopped() {
+                    break;
+                }
+                let result = scan_one(&keys, &note, filter.as_deref(), now, logger.as_deref(), &policy);
+                if result_tx.blocking_send((seq, result)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Output {
+            results: result_rx,
+            ready: VecDeque::new(),
+            reorder: self.options.ordered.then(Reorder::default),
+            seen: RecentIds::new(self.options.dedupe_window),
+            stop,
+            done: false,
+        }
+    }
+}
+
+fn scan_one(
+    keys: &[ScanKey],
+    note: &EncryptedNote,
+    filter: Option<&str>,
+    now: i64,
+    logger: Option<&(dyn ExternalLogger + Send + Sync)>,
+    policy: &RedactionPolicy,
+) -> Vec<ScanItem> {
+    let logger = logger.map(|l| l as &dyn ExternalLogger);
+    keys.iter()
+        .filter_map(|key| decrypt_note(key, note, filter, now, logger, policy).transpose())
+        .collect()
+}
+
+type ScanItem = Result<DecryptedNote, NoteError>;
+
+/// Holds results that finished ahead of an earlier note until that note is done.
+#[derive(Default)]
+struct Reorder {
+    next: u64,
+    pending: BTreeMap<u64, Vec<ScanItem>>,
+}
+
+/// Output side shared by the blocking and async front ends.
+struct Output {
+    results: async_mpsc::Receiver<(u64, Vec<ScanItem>)>,
+    ready: VecDeque<ScanItem>,
+    reorder: Option<Reorder>,
+    seen: RecentIds,
+    stop: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Output {
+    fn accept(&mut self, seq: u64, result: Vec<ScanItem>) {
+        let Some(reorder) = &mut self.reorder else {
+            release(&mut self.ready, &mut self.seen, result);
+            return;
+        };
+        reorder.pending.insert(seq, result);
+        while let Some(result) = reorder.pending.remove(&reorder.next) {
+            reorder.next += 1;
+            release(&mut self.ready, &mut self.seen, result);
+        }
+    }
+
+    fn take(&mut self) -> Option<ScanItem> {
+        self.ready.pop_front()
+    }
+}
+
+/// Ids of the last `capacity` notes yielded.
+struct RecentIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    fn new(capacity: usize) -> Self {
+        RecentIds { ids: HashSet::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// Remember `id`, forgetting the oldest id once full. False if `id` is already remembered.
+    fn insert(&mut self, id: &str) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+fn release(ready: &mut VecDeque<ScanItem>, seen: &mut RecentIds, result: Vec<ScanItem>) {
+    ready.extend(result.into_iter().filter(|item| match item {
+        Ok(note) => seen.insert(&note.note_id),
+        Err(_) => true,
+    }));
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Blocking iterator over scan results. Must not be driven from inside an async runtime; use
+/// [`NoteScanner::scan_stream`] there.
+pub struct ScanResults(Output);
+
+impl Iterator for ScanResults {
+    type Item = ScanItem;
+
+    fn next(&mut self) -> Option<ScanItem> {
+        let out = &mut self.0;
+        loop {
+            if let Some(item) = out.take() {
+                return Some(item);
+            }
+            if out.done {
+                return None;
+            }
+            match out.results.blocking_recv() {
+                Some((seq, result)) => out.accept(seq, result),
+                None => out.done = true,
+            }
+        }
+    }
+}
+
+/// Async stream of scan results.
+pub struct ScanStream(Output);
+
+impl Stream for ScanStream {
+    type Item = ScanItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ScanItem>> {
+        let out = &mut self.get_mut().0;
+        loop {
+            if let Some(item) = out.take() {
+                return Poll::Ready(Some(item));
+            }
+            if out.done {
+                return Poll::Ready(None);
+            }
+            match out.results.poll_recv(cx) {
+                Poll::Ready(Some((seq, result))) => out.accept(seq, result),
+                Poll::Ready(None) => out.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_notes::NotePayload;
+    use crate::encrypt::encrypt_note;
+    use crate::keys::SpendingKey;
+    use futures::StreamExt;
+
+    fn notes(view_key: &IncomingViewKey, count: u64) -> Vec<EncryptedNote> {
+        let other = SpendingKey([9u8; 32]).incoming_view_key();
+        (0..count)
+            .map(|i| {
+                // Every third note belongs to someone else.
+                let owner = if i % 3 == 2 { &other } else { view_key };
+                let payload = NotePayload { amount: i, recipient: "alice".to_string(), memo: None, ..NotePayload::default() };
+                encrypt_note(&payload, &owner.address_for("alice"), &format!("note{}", i), None).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_repeats_are_dropped_within_the_window() {
+        let view_key = SpendingKey([4u8; 32]).incoming_view_key();
+        let n = notes(&view_key, 5);
+        let input = vec![n[0].clone(), n[0].clone(), n[1].clone(), n[3].clone(), n[4].clone(), n[0].clone()];
+        let amounts = |dedupe_window: usize| -> Vec<u64> {
+            let scanner = NoteScanner::new(std::slice::from_ref(&view_key), ScanOptions { dedupe_window, ..options(true) });
+            scanner.scan_iter(input.clone(), &CancelToken::new()).map(|note| note.unwrap().parsed.amount).collect()
+        };
+        // With room for two ids, the last copy of note 0 comes after it has been forgotten.
+        assert_eq!(amounts(2), vec![0, 1, 3, 4, 0]);
+        assert_eq!(amounts(16), vec![0, 1, 3, 4]);
+        assert_eq!(amounts(0), vec![0, 0, 1, 3, 4, 0]);
+    }
+
+    fn options(ordered: bool) -> ScanOptions {
+        ScanOptions { workers: 4, ordered, now: 0, buffer: 8, ..ScanOptions::default() }
+    }
+
+    #[test]
+    fn test_ordered_scan_preserves_input_order() {
+        let view_key = SpendingKey([1u8; 32]).incoming_view_key();
+        let scanner = NoteScanner::new(std::slice::from_ref(&view_key), options(true));
+        let amounts: Vec<u64> = scanner
+            .scan_iter(notes(&view_key, 60), &CancelToken::new())
+            .map(|note| note.unwrap().parsed.amount)
+            .collect();
+        let expected: Vec<u64> = (0..60).filter(|i| i % 3 != 2).collect();
+        assert_eq!(amounts, expected);
+    }
+
+    #[test]
+    fn test_unordered_stream_finds_every_note() {
+        let view_key = SpendingKey([2u8; 32]).incoming_view_key();
+        let scanner = NoteScanner::new(std::slice::from_ref(&view_key), options(false));
+        let input = futures::stream::iter(notes(&view_key, 30));
+        let mut amounts: Vec<u64> = tokio_test::block_on(
+            scanner
+                .scan_stream(input, &CancelToken::new())
+                .map(|note| note.unwrap().parsed.amount)
+                .collect(),
+        );
+        amounts.sort_unstable();
+        assert_eq!(amounts, (0..30).filter(|i| i % 3 != 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cancel_ends_the_scan_and_bad_notes_do_not() {
+        let view_key = SpendingKey([3u8; 32]).incoming_view_key();
+        let scanner = NoteScanner::new(std::slice::from_ref(&view_key), options(true));
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert_eq!(scanner.scan_iter(notes(&view_key, 30), &cancel).count(), 0);
+
+        let mut input = notes(&view_key, 6);
+        input[3].ciphertext.truncate(3);
+        let results: Vec<_> = scanner.scan_iter(input.clone(), &CancelToken::new()).collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[2], Err(NoteError::MalformedCiphertext { .. })));
+        assert_eq!(results[3].as_ref().unwrap().parsed.amount, 4);
+
+        input[4].metadata = "tampered".to_string();
+        input.extend(notes(&view_key, 7).into_iter().skip(6));
+        let results: Vec<_> = scanner.scan_iter(input, &CancelToken::new()).collect();
+        assert_eq!(results.len(), 5);
+        assert!(matches!(results[3], Err(NoteError::TamperedMetadata { .. })));
+        assert_eq!(results[4].as_ref().unwrap().parsed.amount, 6);
+    }
+}